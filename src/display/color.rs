@@ -1,9 +1,75 @@
+use crate::parser::Color;
 use minifb::clamp;
 
+/// An opaque color as a pixel.
 pub fn rgb_to_u32(red: usize, green: usize, blue: usize) -> u32 {
     let r = clamp(0, red, 255);
     let g = clamp(0, green, 255);
     let b = clamp(0, blue, 255);
-    ((r << 16) | (g << 8) | b) as u32
+    (0xff00_0000 | (r << 16) | (g << 8) | b) as u32
 }
 
+/// Pixels are premultiplied `ARGB`: every channel has already been scaled by
+/// the alpha, so compositing is one multiply-add per channel and layers can
+/// be faded as a whole.
+fn channels(pixel: u32) -> [u32; 4] {
+    [
+        pixel >> 24,
+        (pixel >> 16) & 0xff,
+        (pixel >> 8) & 0xff,
+        pixel & 0xff,
+    ]
+}
+
+fn from_channels(channels: [u32; 4]) -> u32 {
+    channels[0] << 24 | channels[1] << 16 | channels[2] << 8 | channels[3]
+}
+
+/// Multiplies two bytes as fractions of 255, rounding to nearest.
+fn scale(value: u32, factor: u32) -> u32 {
+    let product = value * factor + 128;
+    (product + (product >> 8)) >> 8
+}
+
+/// `color` as a pixel, with its alpha further scaled by `coverage`, as for
+/// an anti-aliased edge.
+pub fn premultiply(color: Color, coverage: u8) -> u32 {
+    let alpha = scale(color.alpha as u32, coverage as u32);
+    from_channels([
+        alpha,
+        scale(color.red as u32, alpha),
+        scale(color.green as u32, alpha),
+        scale(color.blue as u32, alpha),
+    ])
+}
+
+/// Composites `source` over `destination`.
+pub fn over(destination: u32, source: u32) -> u32 {
+    let (source, destination) = (channels(source), channels(destination));
+    let transparency = 255 - source[0];
+    from_channels([0, 1, 2, 3].map(|i| source[i] + scale(destination[i], transparency)))
+}
+
+/// Scales every channel of `pixel` by `opacity`, out of 255.
+pub fn fade(pixel: u32, opacity: u8) -> u32 {
+    from_channels(channels(pixel).map(|channel| scale(channel, opacity as u32)))
+}
+
+#[cfg(test)]
+mod color_tests {
+    use super::*;
+
+    #[test]
+    fn compositing() {
+        let translucent = premultiply(Color::rgba(255, 0, 0, 128), 255);
+        assert_eq!(translucent, 0x8080_0000);
+        assert_eq!(premultiply(Color::rgba(255, 0, 0, 255), 128), 0x8080_0000);
+        assert_eq!(
+            over(rgb_to_u32(0, 0, 255), translucent),
+            rgb_to_u32(128, 0, 127)
+        );
+        assert_eq!(over(0, translucent), translucent);
+        assert_eq!(fade(rgb_to_u32(255, 255, 255), 0), 0);
+        assert_eq!(fade(rgb_to_u32(255, 255, 255), 51), 0x3333_3333);
+    }
+}
@@ -0,0 +1,33 @@
+use crate::dom::StyleError;
+use crate::parser::ParseError;
+use std::fmt;
+
+/// An error found while rendering a document without a window.
+#[derive(Debug, PartialEq, Clone)]
+pub enum RenderError {
+    Parse(ParseError),
+    Style(StyleError),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::Parse(e) => write!(f, "{}", e),
+            RenderError::Style(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+impl From<ParseError> for RenderError {
+    fn from(e: ParseError) -> Self {
+        RenderError::Parse(e)
+    }
+}
+
+impl From<StyleError> for RenderError {
+    fn from(e: StyleError) -> Self {
+        RenderError::Style(e)
+    }
+}
@@ -0,0 +1,486 @@
+use super::error::RenderError;
+use super::scene::Scene;
+use crate::dom::render_tree::RenderNode;
+use crate::dom::{
+    add_text_constraints, construct_style_tree, generate_render_tree, generate_variable_pool,
+    reflow_layout, reflow_text, resize_window, solve_constraints, DefaultConstraints, MeasureText,
+    StyleError, StyleTree, VariablePool, BOX_ATTRIBUTES, PROPERTY_NAMES, TEXT_STRENGTH,
+};
+use crate::parser::asml_parser::Element;
+use crate::parser::ass_parser::Stylesheet;
+use crate::parser::{parser, ParseError};
+use cassowary::Solver;
+use std::collections::{HashMap, HashSet};
+
+/// A parsed document, along with the attribute names and default constraints
+/// its style tree borrows.
+pub struct Document {
+    body: Element,
+    stylesheet: Stylesheet,
+    constraint_names: HashSet<String>,
+    property_names: HashSet<String>,
+    defaults: DefaultConstraints,
+}
+
+impl Document {
+    pub fn parse(source: &str) -> Result<Document, ParseError> {
+        let (body, stylesheet) = parser(source)?;
+        Ok(Document {
+            body,
+            stylesheet,
+            constraint_names: BOX_ATTRIBUTES.iter().map(|name| name.to_string()).collect(),
+            property_names: PROPERTY_NAMES.iter().map(|name| name.to_string()).collect(),
+            defaults: DefaultConstraints::default(),
+        })
+    }
+
+    /// Styles the document and solves its layout in a window of the given
+    /// size, measuring text with `measure`.
+    pub fn lay_out(
+        &self,
+        measure: &dyn MeasureText,
+        width: usize,
+        height: usize,
+    ) -> Result<DocumentLayout<'_>, StyleError> {
+        let style_tree = construct_style_tree(
+            &self.body,
+            &self.stylesheet,
+            &self.constraint_names,
+            &self.property_names,
+            &self.defaults,
+        )?;
+        let mut solver = Solver::new();
+        let mut variable_pool = HashMap::new();
+        generate_variable_pool(&style_tree, &mut variable_pool, &mut solver);
+        add_text_constraints(
+            &style_tree,
+            &mut variable_pool,
+            &mut solver,
+            measure,
+            TEXT_STRENGTH,
+        );
+        resize_window(&mut variable_pool, &mut solver, width as f64, height as f64)?;
+        solve_constraints(&style_tree, &mut variable_pool, &mut solver)?;
+        let mut layout = DocumentLayout {
+            style_tree,
+            variable_pool,
+            solver,
+        };
+        layout.reflow(measure)?;
+        Ok(layout)
+    }
+}
+
+/// The style tree of a document and the solver state laying it out.
+pub struct DocumentLayout<'a> {
+    pub style_tree: StyleTree<'a>,
+    pub variable_pool: VariablePool,
+    pub solver: Solver,
+}
+
+impl<'a> DocumentLayout<'a> {
+    /// Solves the layout again in a window of a new size. Returns whether
+    /// anything moved, i.e. whether the render tree has to be regenerated.
+    pub fn resize(
+        &mut self,
+        measure: &dyn MeasureText,
+        width: usize,
+        height: usize,
+    ) -> Result<bool, StyleError> {
+        let resized = resize_window(
+            &mut self.variable_pool,
+            &mut self.solver,
+            width as f64,
+            height as f64,
+        )?;
+        if resized {
+            self.reflow(measure)?;
+        }
+        Ok(resized)
+    }
+
+    pub fn render_tree(&mut self) -> Result<RenderNode<'a>, StyleError> {
+        generate_render_tree(&self.style_tree, &self.solver, &mut self.variable_pool)
+    }
+
+    /// Wraps text and breaks wrapping layouts into rows at the solved sizes.
+    fn reflow(&mut self, measure: &dyn MeasureText) -> Result<(), StyleError> {
+        reflow_text(
+            &self.style_tree,
+            &mut self.variable_pool,
+            &mut self.solver,
+            measure,
+        )?;
+        reflow_layout(&self.style_tree, &mut self.variable_pool, &mut self.solver)?;
+        Ok(())
+    }
+}
+
+/// Runs the whole pipeline on the source of a document and draws it into a
+/// scene of the given size, without opening a window.
+pub fn render_document(source: &str, width: usize, height: usize) -> Result<Scene, RenderError> {
+    let document = Document::parse(source)?;
+    // The scene's font also measures text for the solver
+    let mut scene = Scene::new(width, height);
+    let mut layout = document.lay_out(&scene, width, height)?;
+    let render_tree = layout.render_tree()?;
+    scene.process_render_tree(&render_tree);
+    Ok(scene)
+}
+
+#[cfg(test)]
+mod headless_tests {
+    use super::*;
+    use crate::display::rgb_to_u32;
+
+    #[test]
+    fn png() {
+        let scene = render_document(
+            "<style>a { background_color = #6464c8 width = 50vw height = 50vh }</style><body><a/></body>",
+            40,
+            30,
+        )
+        .unwrap();
+        let mut png = Vec::new();
+        scene.write_png(&mut png).unwrap();
+        let decoder = png::Decoder::new(png.as_slice());
+        let (info, mut reader) = decoder.read_info().unwrap();
+        assert_eq!((info.width, info.height), (40, 30));
+        let mut pixels = vec![0; info.buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(pixels.len(), 40 * 30 * 3);
+        assert_ne!(pixels[..3], pixels[pixels.len() - 3..]);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            render_document("<body>", 10, 10),
+            Err(RenderError::Parse(_))
+        ));
+        assert!(matches!(
+            render_document(
+                "<style>a { width = $b[width] }</style><body><a/></body>",
+                10,
+                10
+            ),
+            Err(RenderError::Style(_))
+        ));
+    }
+
+    #[test]
+    fn resize() {
+        let document =
+            Document::parse("<style>a { width = 50vw height = 10 }</style><body><a/></body>")
+                .unwrap();
+        let scene = Scene::new(100, 100);
+        let mut layout = document.lay_out(&scene, 100, 100).unwrap();
+        assert_eq!(
+            layout.render_tree().unwrap().children[0].attrs.rect.width,
+            50.0
+        );
+        assert!(layout.resize(&scene, 300, 100).unwrap());
+        assert_eq!(
+            layout.render_tree().unwrap().children[0].attrs.rect.width,
+            150.0
+        );
+        assert!(!layout.resize(&scene, 300, 100).unwrap());
+    }
+
+    #[test]
+    fn example_document() {
+        let source = include_str!("../../documents/examples/ex1.ass");
+        let scene = render_document(source, 320, 240).unwrap();
+        assert_eq!(scene.buffer().len(), 320 * 240);
+    }
+
+    #[test]
+    fn text_in_parent_box() {
+        let scene = render_document(
+            "<style>a { background_color = #6464c8 left = 10 top = 10 width = 100 height = 40 }</style>
+            <body><a>Hello there, this wraps</a></body>",
+            200,
+            100,
+        )
+        .unwrap();
+        let text = |x: usize, y: usize| {
+            let pixel = scene.buffer()[y * scene.width() + x];
+            pixel != u32::MAX && pixel != rgb_to_u32(100, 100, 200)
+        };
+        let inside = (10..110).flat_map(|x| (10..50).map(move |y| (x, y)));
+        assert!(inside.clone().any(|(x, y)| text(x, y)));
+        let outside = (0..200)
+            .flat_map(|x| (0..100).map(move |y| (x, y)))
+            .filter(|&(x, y)| !(10..110).contains(&x) || !(10..50).contains(&y));
+        assert!(!outside.into_iter().any(|(x, y)| text(x, y)));
+    }
+
+    #[test]
+    fn mixed_text_and_tags() {
+        let source = |content| {
+            format!(
+                "<style>
+                    a {{ width = 100 layout = stack }}
+                    b {{ width = 100 height = 10 background_color = #00f }}
+                </style>
+                <body><a>{}</a></body>",
+                content
+            )
+        };
+        let mixed = render_document(&source("first run <b/> second run"), 100, 100).unwrap();
+        let plain = render_document(&source("first run second run"), 100, 100).unwrap();
+        // The runs are drawn as one paragraph, as if the tag weren't there
+        let top = mixed
+            .buffer()
+            .iter()
+            .position(|&pixel| pixel == rgb_to_u32(0, 0, 255))
+            .unwrap()
+            / 100;
+        assert!(plain.buffer()[..top * 100]
+            .iter()
+            .any(|&pixel| pixel != u32::MAX));
+        assert_eq!(mixed.buffer()[..top * 100], plain.buffer()[..top * 100]);
+        // and the tag below it
+        assert!(plain.buffer()[top * 100..]
+            .iter()
+            .all(|&pixel| pixel == u32::MAX));
+    }
+
+    /// The pixels painted in the box color, as a sorted list of coordinates.
+    fn painted(source: &str, width: usize, height: usize) -> Vec<(usize, usize)> {
+        let scene = render_document(source, width, height).unwrap();
+        let mut painted = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .filter(|&(x, y)| scene.buffer()[y * width + x] == rgb_to_u32(100, 100, 200))
+            .collect::<Vec<_>>();
+        painted.sort();
+        painted
+    }
+
+    fn area(left: usize, top: usize, right: usize, bottom: usize) -> Vec<(usize, usize)> {
+        (left..right)
+            .flat_map(|x| (top..bottom).map(move |y| (x, y)))
+            .collect()
+    }
+
+    #[test]
+    fn rect_coverage() {
+        assert_eq!(
+            painted(
+                "<style>a { background_color = #6464c8 x = 20 width = 20 y = 10 height = 5 }</style><body><a></a></body>",
+                100,
+                50
+            ),
+            area(20, 10, 40, 15)
+        );
+    }
+
+    #[test]
+    fn nested_rect_coverage() {
+        assert_eq!(
+            painted(
+                "<style>
+                    a { background_color = #6464c8 left = 10 top = 10 width = 30 height = 30 }
+                    b { background_color = #6464c8 left = $parent[left] + 10 top = $parent[bottom] width = 5 height = 10 }
+                </style>
+                <body><a><b></b></a></body>",
+                100,
+                100
+            ),
+            {
+                let mut expected = area(10, 10, 40, 40);
+                expected.extend(area(20, 40, 25, 50));
+                expected.sort();
+                expected
+            }
+        );
+    }
+
+    #[test]
+    fn offscreen_rect_coverage() {
+        assert_eq!(
+            painted(
+                "<style>a { background_color = #6464c8 left = -5 top = 45 width = 10 height = 10 }</style><body><a></a></body>",
+                50,
+                50
+            ),
+            area(0, 45, 5, 50)
+        );
+    }
+
+    #[test]
+    fn inverted_rect() {
+        assert!(painted(
+            "<style>a { background_color = #6464c8 left = 30 top = 10 width = -20 height = 10 }</style><body><a></a></body>",
+            50,
+            50
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn overflow_hidden() {
+        let source = |overflow| {
+            format!(
+                "<style>
+                    a {{ background_color = #6464c8 left = 10 top = 10 width = 20 height = 20 {} }}
+                    b {{ background_color = #6464c8 left = 0 top = 0 width = 100 height = 100 }}
+                </style>
+                <body><a><b></b></a></body>",
+                overflow
+            )
+        };
+        assert_eq!(painted(&source(""), 50, 50), area(0, 0, 50, 50));
+        assert_eq!(
+            painted(&source("overflow = hidden"), 50, 50),
+            area(10, 10, 30, 30)
+        );
+    }
+
+    #[test]
+    fn clipped_text() {
+        let scene = render_document(
+            "<style>a { background_color = #6464c8 left = 10 top = 10 width = 30 height = 10 overflow = hidden }</style>
+            <body><a>Hello there, this wraps</a></body>",
+            100,
+            100,
+        )
+        .unwrap();
+        let outside = (0..100)
+            .flat_map(|x| (0..100).map(move |y| (x, y)))
+            .filter(|&(x, y)| !(10..40).contains(&x) || !(10..20).contains(&y));
+        assert!(outside
+            .into_iter()
+            .all(|(x, y)| scene.buffer()[y * 100 + x] == u32::MAX));
+    }
+
+    #[test]
+    fn text_color() {
+        let scene = render_document(
+            "<style>a { width = 100 height = 40 color = #ff0000 }</style><body><a>Hello</a></body>",
+            100,
+            40,
+        )
+        .unwrap();
+        assert!(scene.buffer().contains(&rgb_to_u32(255, 0, 0)));
+        // Edges blend towards white, so only red varies independently
+        assert!(scene
+            .buffer()
+            .iter()
+            .all(|pixel| pixel & 0xff == (pixel >> 8) & 0xff));
+    }
+
+    fn pixel(source: &str, x: usize, y: usize) -> u32 {
+        let scene = render_document(source, 50, 50).unwrap();
+        scene.buffer()[y * 50 + x]
+    }
+
+    #[test]
+    fn translucent_background() {
+        let source = "<style>
+            a { width = 20 height = 20 background_color = #f00 }
+            b { width = 10 height = 20 background_color = rgba(0, 0, 255, 50%) }
+        </style>
+        <body><a><b></b></a></body>";
+        assert_eq!(pixel(source, 5, 5), rgb_to_u32(127, 0, 128));
+        assert_eq!(pixel(source, 15, 5), rgb_to_u32(255, 0, 0));
+        assert_eq!(pixel(source, 25, 5), u32::MAX);
+    }
+
+    #[test]
+    fn subtree_opacity() {
+        // The children overlap, but only the one on top shows through
+        let source = "<style>
+            a { opacity = .5 }
+            b { width = 20 height = 20 background_color = #f00 }
+            c { left = 10 width = 20 height = 20 background_color = #00f }
+        </style>
+        <body><a><b></b><c></c></a></body>";
+        assert_eq!(pixel(source, 5, 5), rgb_to_u32(255, 127, 127));
+        assert_eq!(pixel(source, 15, 5), rgb_to_u32(127, 127, 255));
+        assert_eq!(pixel(source, 35, 5), u32::MAX);
+    }
+
+    #[test]
+    fn text_over_background() {
+        let scene = render_document(
+            "<style>a { width = 100 height = 40 background_color = #000 color = #fff }</style>
+            <body><a>Hello</a></body>",
+            100,
+            40,
+        )
+        .unwrap();
+        assert!(scene.buffer().contains(&rgb_to_u32(255, 255, 255)));
+        assert!(scene
+            .buffer()
+            .iter()
+            .any(|&pixel| pixel != rgb_to_u32(0, 0, 0) && pixel != rgb_to_u32(255, 255, 255)));
+    }
+
+    #[test]
+    fn rounded_corners() {
+        let source = "<style>
+            a { width = 20 height = 20 background_color = #f00 border_radius = 10
+                border_bottom_right_radius = 0 }
+        </style>
+        <body><a></a></body>";
+        assert_eq!(pixel(source, 0, 0), u32::MAX);
+        assert_eq!(pixel(source, 10, 10), rgb_to_u32(255, 0, 0));
+        assert_eq!(pixel(source, 19, 19), rgb_to_u32(255, 0, 0));
+        let edge = pixel(source, 1, 5);
+        assert!(edge != u32::MAX && edge != rgb_to_u32(255, 0, 0));
+    }
+
+    #[test]
+    fn borders() {
+        let source = |style| {
+            format!(
+                "<style>a {{ width = 20 height = 20 border_width = 2 color = #00f {} }}</style>
+                <body><a></a></body>",
+                style
+            )
+        };
+        let solid = source("");
+        assert_eq!(pixel(&solid, 10, 0), rgb_to_u32(0, 0, 255));
+        assert_eq!(pixel(&solid, 19, 10), rgb_to_u32(0, 0, 255));
+        assert_eq!(pixel(&solid, 10, 2), u32::MAX);
+        let dashed = source("border_style = dashed border_color = #000");
+        assert_eq!(pixel(&dashed, 1, 0), rgb_to_u32(0, 0, 0));
+        assert_eq!(pixel(&dashed, 5, 0), u32::MAX);
+        assert_eq!(pixel(&dashed, 9, 1), rgb_to_u32(0, 0, 0));
+    }
+
+    #[test]
+    fn shadows() {
+        let source = |blur| {
+            format!(
+                "<style>
+                    a {{ left = 10 top = 10 width = 20 height = 20 background_color = #fff
+                        shadow_x = 5 shadow_y = 5 shadow_blur = {} shadow_color = #000 }}
+                </style>
+                <body><a></a></body>",
+                blur
+            )
+        };
+        let sharp = source(0);
+        assert_eq!(pixel(&sharp, 32, 32), rgb_to_u32(0, 0, 0));
+        assert_eq!(pixel(&sharp, 20, 20), rgb_to_u32(255, 255, 255));
+        assert_eq!(pixel(&sharp, 12, 32), u32::MAX);
+        let blurred = source(10);
+        assert!(pixel(&blurred, 36, 20) != pixel(&sharp, 36, 20));
+        let edge = pixel(&blurred, 35, 20);
+        assert!(edge != u32::MAX && edge != rgb_to_u32(0, 0, 0));
+    }
+
+    #[test]
+    fn shadow_under_translucent_background() {
+        let source = "<style>
+            a { left = 10 top = 10 width = 20 height = 20 background_color = rgba(0, 0, 255, 50%)
+                shadow_x = 5 shadow_y = 5 shadow_color = #000 }
+        </style>
+        <body><a></a></body>";
+        // The shadow doesn't show through the box
+        assert_eq!(pixel(source, 25, 25), rgb_to_u32(127, 127, 255));
+        assert_eq!(pixel(source, 32, 32), rgb_to_u32(0, 0, 0));
+    }
+}
@@ -1,5 +1,12 @@
 pub(crate) mod scene;
 pub(crate) mod color;
+pub(crate) mod error;
+pub(crate) mod headless;
+pub(crate) mod shape;
 pub use scene::Scene;
+pub use error::RenderError;
+pub use headless::render_document;
+pub use headless::Document;
+pub use headless::DocumentLayout;
 pub use color::rgb_to_u32;
 
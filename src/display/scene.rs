@@ -1,14 +1,23 @@
-use super::color::rgb_to_u32;
-use crate::dom::render_tree::RenderNode;
+use super::color::{fade, over, premultiply};
+use super::shape::{blurred_coverage, coverage, distance, on_dash, outset, Radii};
+use crate::dom::render_tree::{RenderData, RenderNode};
+use crate::dom::{MeasureText, PropertyValue, Rect};
 use crate::parser::asml_parser::Element;
+use crate::parser::Color;
 use fontdue::layout::{CoordinateSystem, Layout, LayoutSettings, TextStyle};
 use fontdue::Font;
+use std::io::Write;
 
 pub struct Scene {
     width: usize,
     height: usize,
     buffer: Vec<u32>,
     font: Font,
+    /// Each entry is the intersection of the rect pushed and the entry below
+    clip_stack: Vec<Rect>,
+    /// Transparent buffers that subtrees with an opacity are drawn into
+    /// before being faded onto the one below, innermost last
+    layers: Vec<Vec<u32>>,
 }
 
 impl Scene {
@@ -22,6 +31,8 @@ impl Scene {
             height,
             buffer,
             font,
+            clip_stack: Vec::new(),
+            layers: Vec::new(),
         }
     }
 
@@ -31,6 +42,33 @@ impl Scene {
             .unwrap();
     }
 
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Pixels in rows from the top left, as premultiplied `ARGB`. The scene
+    /// starts out opaque white, so the alpha is always 255.
+    pub fn buffer(&self) -> &[u32] {
+        &self.buffer
+    }
+
+    /// Encodes the buffer as an 8 bit RGB PNG.
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(writer, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        let data = self
+            .buffer
+            .iter()
+            .flat_map(|pixel| pixel.to_be_bytes()[1..].to_vec())
+            .collect::<Vec<u8>>();
+        encoder.write_header()?.write_image_data(&data)
+    }
+
     pub fn clear(&mut self) {
         self.buffer.iter_mut().for_each(|x| *x = u32::MAX);
     }
@@ -43,61 +81,328 @@ impl Scene {
         }
     }
 
-    pub fn add_text(&mut self, content: &str, px: f32, left: f32, top: f32, right: f32, bottom: f32) {
+    /// Clips everything drawn until the matching `pop_clip` to `rect`, on top
+    /// of any clip already active.
+    pub fn push_clip(&mut self, rect: &Rect) {
+        let rect = match self.clip_stack.last() {
+            Some(clip) => rect.intersect(clip),
+            None => *rect,
+        };
+        self.clip_stack.push(rect);
+    }
+
+    pub fn pop_clip(&mut self) {
+        self.clip_stack.pop();
+    }
+
+    /// Draws everything until the matching `pop_layer` into a separate
+    /// layer, so that it can be faded as a whole.
+    pub fn push_layer(&mut self) {
+        self.layers.push(vec![0; self.width * self.height]);
+    }
+
+    /// Composites the innermost layer onto whatever is below it, scaled by
+    /// `opacity` between 0 and 1.
+    pub fn pop_layer(&mut self, opacity: f64) {
+        let layer = self.layers.pop().expect("pop_layer without push_layer");
+        let opacity = (opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+        for (destination, source) in self.target().iter_mut().zip(layer) {
+            if source != 0 {
+                *destination = over(*destination, fade(source, opacity));
+            }
+        }
+    }
+
+    /// The buffer drawing goes to.
+    fn target(&mut self) -> &mut [u32] {
+        self.layers.last_mut().unwrap_or(&mut self.buffer)
+    }
+
+    /// The pixels whose top left corner is in `rect`, the active clip and the
+    /// scene, as a `(left, top, right, bottom)` range with exclusive ends.
+    fn pixel_bounds(&self, rect: &Rect) -> (usize, usize, usize, usize) {
+        let rect = match self.clip_stack.last() {
+            Some(clip) => rect.intersect(clip),
+            None => *rect,
+        };
+        // Solved edges can be off by rounding errors, as in 199.99998, so
+        // they're rounded rather than truncated. Casting saturates, so
+        // negative edges become 0
+        let right = (rect.right().round() as usize).min(self.width);
+        let bottom = (rect.bottom().round() as usize).min(self.height);
+        let left = (rect.left.round() as usize).min(right);
+        let top = (rect.top.round() as usize).min(bottom);
+        (left, top, right, bottom)
+    }
+
+    fn layout_text(
+        &self,
+        content: &str,
+        px: f32,
+        x: f32,
+        y: f32,
+        max_width: Option<f32>,
+        max_height: Option<f32>,
+    ) -> Layout {
         let mut layout = Layout::new(CoordinateSystem::PositiveYDown);
         layout.reset(&LayoutSettings {
-            x: left,
-            y: top,
-            max_width: Some(right - left),
-            max_height: Some(bottom - top),
+            x,
+            y,
+            max_width,
+            max_height,
             ..LayoutSettings::default()
         });
-        layout.append(&[&self.font], &TextStyle::new(&content, px, 0));
+        layout.append(&[&self.font], &TextStyle::new(content, px, 0));
+        layout
+    }
+
+    /// Lays out text in `rect`, wrapping at its width unless it has none.
+    pub fn add_text(&mut self, content: &str, px: f32, rect: &Rect, color: Color) {
+        // Fonts can't be rasterized at these sizes
+        if px.is_nan() || px <= 0.0 {
+            return;
+        }
+        // Glyphs are rounded to whole pixels, so a rounding error from the
+        // solver on a half pixel would otherwise move them a pixel either way
+        let snap = |position: f64| ((position * 1024.0).round() / 1024.0) as f32;
+        let layout = self.layout_text(
+            content,
+            px,
+            snap(rect.left),
+            snap(rect.top),
+            Some(rect.width as f32).filter(|width| *width > 0.0),
+            Some(rect.height as f32).filter(|height| *height > 0.0),
+        );
         for glyph in layout.glyphs() {
-            let (_, bitmap) = self.font.rasterize(glyph.key.c, px);
-            if glyph.height != 0 {
-                for j in 0..=glyph.height - 1 {
-                    let start_x = (j + glyph.y as usize) * self.width + glyph.x as usize;
-                    for i in 0..=glyph.width - 1 {
-                        let gray = bitmap[j * glyph.width + i] as usize;
-                        let color = rgb_to_u32(gray, gray, gray);
-                        self.buffer[start_x + i] = self.buffer[start_x + i].saturating_sub(color)
-                    }
+            // Spaces and glyphs at degenerate sizes have nothing to draw
+            if glyph.width == 0 || glyph.height == 0 {
+                continue;
+            }
+            let (_, bitmap) = self.font.rasterize_config(glyph.key);
+            if glyph.width.checked_mul(glyph.height) != Some(bitmap.len()) {
+                continue;
+            }
+            let glyph_rect = Rect {
+                left: glyph.x.round() as f64,
+                top: glyph.y.round() as f64,
+                width: glyph.width as f64,
+                height: glyph.height as f64,
+            };
+            let (left, top, right, bottom) = self.pixel_bounds(&glyph_rect);
+            let width = self.width;
+            let target = self.target();
+            for y in top..bottom {
+                let j = (y as f64 - glyph_rect.top) as usize;
+                for x in left..right {
+                    let i = (x as f64 - glyph_rect.left) as usize;
+                    let pixel = &mut target[y * width + x];
+                    *pixel = over(*pixel, premultiply(color, bitmap[j * glyph.width + i]));
                 }
             }
         }
     }
 
-    pub fn add_rect(&mut self, left: f32, top: f32, right: f32, bottom: f32, color: u32) {
-        for line in (top as usize)..=(bottom as usize) {
-            for pixel in
-                self.buffer[(line * self.width + left as usize)..=(line * self.width + right as usize)].iter_mut()
-            {
-                *pixel = color;
+    /// Fills the pixels whose top left corner is in `rect`, ignoring whatever
+    /// part of it is clipped or outside the scene. Translucent colors are
+    /// composited over what's already there.
+    pub fn add_rect(&mut self, rect: &Rect, color: Color) {
+        let (left, top, right, bottom) = self.pixel_bounds(rect);
+        let (width, source) = (self.width, premultiply(color, 255));
+        let target = self.target();
+        for line in top..bottom {
+            for pixel in target[(line * width + left)..(line * width + right)].iter_mut() {
+                *pixel = over(*pixel, source);
             }
         }
     }
 
-    pub fn process_render_tree(&mut self, root: &RenderNode) {
-        match root.element {
-            Element::Tag {
-                traits: _,
-                children: _,
-            } => {
-                if !root.attrs.constraints.is_empty() {
-                    self.add_rect(
-                        root.attrs.constraints.get(&"x".to_string()).copied().unwrap_or_default() as f32,
-                        root.attrs.constraints.get(&"y".to_string()).copied().unwrap_or_default() as f32,
-                        root.attrs.constraints.get(&"width".to_string()).copied().unwrap_or_default() as f32,
-                        root.attrs.constraints.get(&"height".to_string()).copied().unwrap_or_default() as f32,
-                        root.attrs.constraints.get(&"color".to_string()).copied().map_or(rgb_to_u32(100, 100, 200), |f| f as u32),
-                    )
+    /// Composites `color` over the pixels in `bounds`, scaled by how much of
+    /// each `coverage` says is covered, given the pixel's center.
+    fn fill(&mut self, bounds: &Rect, color: Color, coverage: impl Fn(f64, f64) -> f64) {
+        let (left, top, right, bottom) = self.pixel_bounds(bounds);
+        let width = self.width;
+        let target = self.target();
+        for y in top..bottom {
+            for x in left..right {
+                let coverage = coverage(x as f64 + 0.5, y as f64 + 0.5);
+                if coverage > 0.0 {
+                    let pixel = &mut target[y * width + x];
+                    *pixel = over(*pixel, premultiply(color, (coverage * 255.0).round() as u8));
                 }
             }
-            Element::Text(_) => {}
+        }
+    }
+
+    /// Like `add_rect`, with anti-aliased rounded corners.
+    pub fn add_rounded_rect(&mut self, rect: &Rect, radii: &Radii, color: Color) {
+        if radii.is_zero() {
+            return self.add_rect(rect, color);
+        }
+        self.fill(&outset(rect, 1.0), color, |x, y| {
+            coverage(distance(rect, radii, x, y))
+        });
+    }
+
+    /// Draws a border `width` pixels wide just inside the edge of `rect`.
+    pub fn add_border(
+        &mut self,
+        rect: &Rect,
+        radii: &Radii,
+        width: f64,
+        color: Color,
+        dashed: bool,
+    ) {
+        if width <= 0.0 {
+            return;
+        }
+        let inner = Rect {
+            left: rect.left + width,
+            top: rect.top + width,
+            width: (rect.width - 2.0 * width).max(0.0),
+            height: (rect.height - 2.0 * width).max(0.0),
+        };
+        let inner_radii = radii.shrink(width);
+        self.fill(&outset(rect, 1.0), color, |x, y| {
+            if dashed && !on_dash(rect, x, y, 2.0 * width) {
+                return 0.0;
+            }
+            coverage(distance(rect, radii, x, y))
+                * (1.0 - coverage(distance(&inner, &inner_radii, x, y)))
+        });
+    }
+
+    /// Draws the shadow of `rect` moved by `offset`, blurred by `blur`
+    /// pixels, which is twice the standard deviation of the blur, as in CSS.
+    /// As in CSS, the shadow is only drawn outside `rect`, so it doesn't show
+    /// through a translucent background.
+    pub fn add_shadow(
+        &mut self,
+        rect: &Rect,
+        radii: &Radii,
+        offset: (f64, f64),
+        blur: f64,
+        color: Color,
+    ) {
+        let sigma = blur.max(0.0) / 2.0;
+        let shadow = Rect {
+            left: rect.left + offset.0,
+            top: rect.top + offset.1,
+            ..*rect
+        };
+        // Three standard deviations out, the shadow is all but gone
+        self.fill(&outset(&shadow, 3.0 * sigma + 1.0), color, |x, y| {
+            blurred_coverage(distance(&shadow, radii, x, y), sigma)
+                * (1.0 - coverage(distance(rect, radii, x, y)))
+        });
+    }
+
+    /// Draws the shadow, background and border of a tag, in that order.
+    fn paint_box(&mut self, attrs: &RenderData) {
+        let length = |name| match attrs.properties.get(name) {
+            Some(PropertyValue::Length(length)) | Some(PropertyValue::Number(length)) => {
+                Some(*length)
+            }
+            _ => None,
+        };
+        let color = |name| match attrs.properties.get(name) {
+            Some(PropertyValue::Color(color)) => Some(*color),
+            _ => None,
+        };
+        let radius = length("border_radius").unwrap_or(0.0);
+        let radii = Radii {
+            top_left: length("border_top_left_radius").unwrap_or(radius),
+            top_right: length("border_top_right_radius").unwrap_or(radius),
+            bottom_right: length("border_bottom_right_radius").unwrap_or(radius),
+            bottom_left: length("border_bottom_left_radius").unwrap_or(radius),
+        };
+        let shadow = ["shadow_blur", "shadow_color", "shadow_x", "shadow_y"];
+        if shadow
+            .iter()
+            .any(|name| attrs.properties.contains_key(*name))
+        {
+            self.add_shadow(
+                &attrs.rect,
+                &radii,
+                (
+                    length("shadow_x").unwrap_or(0.0),
+                    length("shadow_y").unwrap_or(0.0),
+                ),
+                length("shadow_blur").unwrap_or(0.0),
+                color("shadow_color").unwrap_or(Color::rgba(0, 0, 0, 128)),
+            );
+        }
+        if let Some(background_color) = color("background_color") {
+            self.add_rounded_rect(&attrs.rect, &radii, background_color);
+        }
+        if let Some(width) = length("border_width") {
+            let dashed = attrs.properties.get("border_style")
+                == Some(&PropertyValue::Keyword("dashed".to_string()));
+            // Borders are the color of the text unless they have their own
+            let border_color = color("border_color").or_else(|| color("color"));
+            self.add_border(
+                &attrs.rect,
+                &radii,
+                width,
+                border_color.unwrap_or(Color::from_u32(0x000000)),
+                dashed,
+            );
+        }
+    }
+
+    pub fn process_render_tree(&mut self, root: &RenderNode) {
+        let layered = root.attrs.opacity < 1.0;
+        if layered {
+            self.push_layer();
+        }
+        // Text nodes are drawn by their tag, as one paragraph
+        if let Element::Tag { .. } = root.element {
+            self.paint_box(&root.attrs);
+        }
+        if root.attrs.clip {
+            self.push_clip(&root.attrs.rect);
+        }
+        if let Some(text) = &root.attrs.text {
+            let color = match root.attrs.properties.get("color") {
+                Some(PropertyValue::Color(color)) => *color,
+                _ => Color::from_u32(0x000000),
+            };
+            self.add_text(text, root.attrs.font_size as f32, &root.attrs.rect, color);
         }
         for child in &root.children {
             self.process_render_tree(child);
         }
+        if root.attrs.clip {
+            self.pop_clip();
+        }
+        if layered {
+            self.pop_layer(root.attrs.opacity);
+        }
+    }
+}
+
+impl MeasureText for Scene {
+    fn measure_text(&self, content: &str, px: f64, max_width: Option<f64>) -> (f64, f64) {
+        let layout = self.layout_text(
+            content,
+            px as f32,
+            0.0,
+            0.0,
+            max_width.map(|width| width as f32),
+            None,
+        );
+        // Wrapping goes by advances, so the width does too, rounded up so
+        // that laying the text out again at this width doesn't wrap it
+        let width = layout
+            .glyphs()
+            .iter()
+            .map(|glyph| {
+                glyph.x
+                    + self
+                        .font
+                        .metrics_indexed(glyph.key.glyph_index, px as f32)
+                        .advance_width
+            })
+            .fold(0.0, f32::max);
+        (width.ceil() as f64, layout.height().ceil() as f64)
     }
 }
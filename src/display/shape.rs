@@ -0,0 +1,165 @@
+use crate::dom::Rect;
+
+/// The radius of each corner of a box, in pixels.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Radii {
+    pub top_left: f64,
+    pub top_right: f64,
+    pub bottom_right: f64,
+    pub bottom_left: f64,
+}
+
+impl Radii {
+    pub fn is_zero(&self) -> bool {
+        *self == Radii::default()
+    }
+
+    /// The radii of the same corners `by` pixels further in, as for the inner
+    /// edge of a border.
+    pub fn shrink(&self, by: f64) -> Radii {
+        let shrink = |radius: f64| (radius - by).max(0.0);
+        Radii {
+            top_left: shrink(self.top_left),
+            top_right: shrink(self.top_right),
+            bottom_right: shrink(self.bottom_right),
+            bottom_left: shrink(self.bottom_left),
+        }
+    }
+}
+
+/// How far `(x, y)` is outside the edge of `rect` with rounded corners,
+/// negative inside. Radii are limited to half the shorter side, so a large
+/// radius makes a pill or a circle.
+pub fn distance(rect: &Rect, radii: &Radii, x: f64, y: f64) -> f64 {
+    let (half_width, half_height) = (rect.width / 2.0, rect.height / 2.0);
+    let (x, y) = (x - (rect.left + half_width), y - (rect.top + half_height));
+    let radius = match (x < 0.0, y < 0.0) {
+        (true, true) => radii.top_left,
+        (false, true) => radii.top_right,
+        (false, false) => radii.bottom_right,
+        (true, false) => radii.bottom_left,
+    }
+    .max(0.0)
+    .min(half_width.min(half_height));
+    // Relative to the center of the corner's circle
+    let (corner_x, corner_y) = (
+        x.abs() - half_width + radius,
+        y.abs() - half_height + radius,
+    );
+    let outside = corner_x.max(0.0).hypot(corner_y.max(0.0));
+    let inside = corner_x.max(corner_y).min(0.0);
+    outside + inside - radius
+}
+
+/// `rect` grown by `by` pixels on every side.
+pub fn outset(rect: &Rect, by: f64) -> Rect {
+    Rect {
+        left: rect.left - by,
+        top: rect.top - by,
+        width: rect.width + 2.0 * by,
+        height: rect.height + 2.0 * by,
+    }
+}
+
+/// Whether `(x, y)`, on the border of `rect`, falls on a dash rather than
+/// on a gap. Dashes and gaps are `length` long and start from the top left
+/// corner along each side. Corners belong to the top and bottom sides.
+pub fn on_dash(rect: &Rect, x: f64, y: f64, length: f64) -> bool {
+    // Ties on the diagonal of a corner are decided with some slack, since
+    // the solver can put an edge a rounding error either side of a pixel
+    let horizontal =
+        (y - rect.top).min(rect.bottom() - y) <= (x - rect.left).min(rect.right() - x) + 1e-6;
+    let along = if horizontal {
+        x - rect.left
+    } else {
+        y - rect.top
+    };
+    (along / length).floor() as i64 % 2 == 0
+}
+
+/// How much of a pixel an edge `distance` away from its center covers,
+/// between 0 and 1.
+pub fn coverage(distance: f64) -> f64 {
+    (0.5 - distance).clamp(0.0, 1.0)
+}
+
+/// Like `coverage`, for an edge blurred by a gaussian with a standard
+/// deviation of `sigma`.
+pub fn blurred_coverage(distance: f64, sigma: f64) -> f64 {
+    if sigma <= 0.0 {
+        return coverage(distance);
+    }
+    0.5 * (1.0 - erf(distance / (sigma * std::f64::consts::SQRT_2)))
+}
+
+/// The error function, to within 1.5e-7 (Abramowitz and Stegun 7.1.26).
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let polynomial = t
+        * (0.254_829_592
+            + t * (-0.284_496_736
+                + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    (1.0 - polynomial * (-x * x).exp()).copysign(x)
+}
+
+#[cfg(test)]
+mod shape_tests {
+    use super::*;
+
+    fn square() -> Rect {
+        Rect {
+            left: 0.0,
+            top: 0.0,
+            width: 20.0,
+            height: 20.0,
+        }
+    }
+
+    #[test]
+    fn square_distance() {
+        let radii = Radii::default();
+        assert_eq!(distance(&square(), &radii, 10.0, 10.0), -10.0);
+        assert_eq!(distance(&square(), &radii, 25.0, 10.0), 5.0);
+        assert_eq!(distance(&square(), &radii, 23.0, 24.0), 5.0);
+        assert_eq!(coverage(distance(&square(), &radii, 19.5, 10.0)), 1.0);
+        assert_eq!(coverage(distance(&square(), &radii, 20.5, 10.0)), 0.0);
+    }
+
+    #[test]
+    fn rounded_distance() {
+        let radii = Radii {
+            top_left: 5.0,
+            bottom_right: 100.0,
+            ..Radii::default()
+        };
+        assert!((distance(&square(), &radii, 0.0, 0.0) - (50f64.sqrt() - 5.0)).abs() < 1e-9);
+        assert_eq!(distance(&square(), &radii, 20.0, 0.0), 0.0);
+        // Limited to a radius of 10, so the corner is a quarter circle
+        assert!((distance(&square(), &radii, 20.0, 20.0) - (200f64.sqrt() - 10.0)).abs() < 1e-9);
+        assert_eq!(distance(&square(), &radii, 10.0, 20.0), 0.0);
+    }
+
+    #[test]
+    fn dashes() {
+        assert!(on_dash(&square(), 1.0, 0.5, 2.0));
+        assert!(!on_dash(&square(), 3.0, 0.5, 2.0));
+        assert!(on_dash(&square(), 5.0, 0.5, 2.0));
+        assert!(!on_dash(&square(), 19.5, 3.0, 2.0));
+        assert!(on_dash(&square(), 19.5, 5.0, 2.0));
+        // On the diagonal of the top right corner, with or without noise
+        assert_eq!(
+            on_dash(&square(), 19.5, 0.5, 2.0),
+            on_dash(&square(), 19.5, 0.5 + 1e-9, 2.0)
+        );
+        assert!(!on_dash(&square(), 19.5, 0.5, 2.0));
+    }
+
+    #[test]
+    fn blur() {
+        assert!((blurred_coverage(0.0, 2.0) - 0.5).abs() < 1e-6);
+        assert!(blurred_coverage(-6.0, 2.0) > 0.998);
+        assert!(blurred_coverage(6.0, 2.0) < 0.002);
+        assert!(blurred_coverage(1.0, 2.0) < blurred_coverage(-1.0, 2.0));
+        assert_eq!(blurred_coverage(0.2, 0.0), coverage(0.2));
+    }
+}
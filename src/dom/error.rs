@@ -0,0 +1,97 @@
+use std::fmt;
+
+/// An error found while turning a parsed document into a style tree.
+#[derive(Debug, PartialEq, Clone)]
+pub enum StyleError {
+    /// An alias expands back into itself, e.g. `a = b` and `b = a`.
+    /// Holds the chain of alias names, starting and ending with the same one.
+    AliasCycle(Vec<String>),
+    /// A parameterized alias or style was given the wrong number of arguments.
+    Arity {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// A constraint refers to a name that is neither an element's id nor a
+    /// trait any element carries.
+    UnknownEntity(String),
+    /// Two elements were given the same `#id`.
+    DuplicateId(String),
+    /// `$prev` or `$next` of an element without such a sibling.
+    NoSibling(String),
+    /// `max(...)` bounding an attribute from above, or `min(...)` from
+    /// below, which only one of several constraints would have to satisfy.
+    /// Holds the offending constraint.
+    Disjunction(String),
+    /// A reference to an attribute no element has, e.g. `$parent[widht]`, or
+    /// one the element referred to lacks, e.g. `intrinsic_width` of a tag
+    /// without text. Holds the offending reference.
+    UnknownAttribute(String),
+    /// A word or string was used where a constraint needs a number.
+    InvalidValue(String),
+    /// An expression multiplies two variables, or divides by one, which a
+    /// linear constraint can't express. Holds the offending expression.
+    NonLinear(String),
+    /// An expression divides by zero. Holds the offending expression.
+    DivisionByZero(String),
+    /// A required constraint contradicts the ones added before it. Holds the
+    /// offending constraint.
+    Unsatisfiable(String),
+    /// The solver failed in a way no document should be able to cause.
+    Solver(String),
+}
+
+impl fmt::Display for StyleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StyleError::AliasCycle(chain) => {
+                write!(f, "alias cycle: {}", chain.join(" -> "))
+            }
+            StyleError::Arity {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{}` takes {} argument{} but {} {} given",
+                name,
+                expected,
+                if *expected == 1 { "" } else { "s" },
+                found,
+                if *found == 1 { "was" } else { "were" }
+            ),
+            StyleError::UnknownEntity(name) => {
+                write!(f, "no element has the id or trait `{}`", name)
+            }
+            StyleError::DuplicateId(name) => {
+                write!(f, "more than one element has the id `{}`", name)
+            }
+            StyleError::NoSibling(entity) => write!(f, "`{}` refers to no element", entity),
+            StyleError::Disjunction(constraint) => write!(
+                f,
+                "`{}` can't be expressed: it holds if any one value does",
+                constraint
+            ),
+            StyleError::UnknownAttribute(reference) => {
+                write!(f, "`{}` refers to an unknown attribute", reference)
+            }
+            StyleError::InvalidValue(value) => {
+                write!(f, "`{}` is not a valid constraint value", value)
+            }
+            StyleError::NonLinear(expression) => write!(
+                f,
+                "`{}` is not linear: only constants can multiply or divide",
+                expression
+            ),
+            StyleError::DivisionByZero(expression) => {
+                write!(f, "`{}` divides by zero", expression)
+            }
+            StyleError::Unsatisfiable(constraint) => {
+                write!(f, "`{}` contradicts other required constraints", constraint)
+            }
+            StyleError::Solver(message) => write!(f, "solver error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for StyleError {}
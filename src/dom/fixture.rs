@@ -0,0 +1,34 @@
+use super::error::StyleError;
+use super::style_tree::{
+    construct_style_tree, generate_variable_pool, resize_window, DefaultConstraints, StyleTree,
+    VariablePool, BOX_ATTRIBUTES, PROPERTY_NAMES,
+};
+use crate::parser::asml_parser::asml_parser::tag;
+use crate::parser::ass_parser::ass_parser::stylesheet;
+use cassowary::Solver;
+use std::collections::{HashMap, HashSet};
+
+/// Styles `markup` with `sheet`, knowing every box attribute and property,
+/// creates its variable pool in an 800x600 window, and hands the tree, pool
+/// and solver to `f` to add constraints, solve and read back. The tree
+/// borrows the parsed documents, hence the callback.
+pub(crate) fn with_style<T>(
+    sheet: &str,
+    markup: &str,
+    defaults: &DefaultConstraints,
+    f: impl FnOnce(&StyleTree, &mut VariablePool, &mut Solver) -> Result<T, StyleError>,
+) -> Result<T, StyleError> {
+    let sheet = stylesheet(sheet).unwrap();
+    let body = tag(markup).unwrap();
+    let constraint_names: HashSet<String> =
+        BOX_ATTRIBUTES.iter().map(|name| name.to_string()).collect();
+    let property_names: HashSet<String> =
+        PROPERTY_NAMES.iter().map(|name| name.to_string()).collect();
+    let style_tree =
+        construct_style_tree(&body, &sheet, &constraint_names, &property_names, defaults)?;
+    let mut solver = Solver::new();
+    let mut variable_pool = HashMap::new();
+    generate_variable_pool(&style_tree, &mut variable_pool, &mut solver);
+    resize_window(&mut variable_pool, &mut solver, 800.0, 600.0)?;
+    f(&style_tree, &mut variable_pool, &mut solver)
+}
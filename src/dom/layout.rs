@@ -0,0 +1,307 @@
+use super::error::StyleError;
+use super::style_tree::{
+    add_constraint, add_edit_variable, arith_to_expression, retrieve_variable, settle,
+    suggest_value, Scope, StyleNode, StyleTree, VariablePool, REFLOW_TOLERANCE,
+};
+use crate::parser::asml_parser::Element;
+use crate::parser::ass_parser::Arith;
+use cassowary::strength::{REQUIRED, WEAK};
+use cassowary::{Expression, Solver, WeightedRelation};
+
+/// Strength of the constraints a layout adds, so that any constraint from
+/// the stylesheet overrides them. Still above `WEAK`, which text asks to be
+/// one line wide at, so text wraps before a layout gives way.
+pub const LAYOUT_STRENGTH: f64 = WEAK * 10.0;
+
+/// How a tag places its children, from its `layout` property.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Layout {
+    /// One below the other
+    Stack,
+    /// Side by side
+    Row,
+    /// Side by side, starting a new row whenever one would get wider than
+    /// the tag
+    Wrap,
+}
+
+/// The value `node` last gives the property `name`.
+fn property<'b>(node: &'b StyleNode, name: &str) -> Option<&'b Arith> {
+    node.styles
+        .properties
+        .iter()
+        .rev()
+        .find(|(attr_name, _)| *attr_name == name)
+        .and_then(|(_, terms)| terms.first())
+        .map(|(_, arith, _)| arith)
+}
+
+fn layout(node: &StyleNode) -> Result<Option<Layout>, StyleError> {
+    Ok(match property(node, "layout") {
+        None => None,
+        Some(Arith::Ident(keyword)) if keyword == "stack" => Some(Layout::Stack),
+        Some(Arith::Ident(keyword)) if keyword == "row" => Some(Layout::Row),
+        Some(Arith::Ident(keyword)) if keyword == "wrap" => Some(Layout::Wrap),
+        Some(arith) => return Err(StyleError::InvalidValue(arith.to_string())),
+    })
+}
+
+/// The tags directly in `node`, which are what a layout places.
+fn tag_children<'b, 'a>(
+    tree: &'b StyleTree<'a>,
+    node: &'b StyleNode<'a>,
+) -> Vec<&'b StyleNode<'a>> {
+    tree.children(node)
+        .filter(|child| matches!(child.element, Element::Tag { .. }))
+        .collect()
+}
+
+/// Places the children of `node` according to its `layout` property, `gap`
+/// pixels apart, and makes `node` at least big enough to cover them. Every
+/// constraint is at `LAYOUT_STRENGTH`.
+///
+/// A wrapping layout puts its children at the edit variables `flow_x` and
+/// `flow_y` from its top left corner, which start out at 0. Call
+/// `reflow_layout` after solving to break the children into rows.
+pub(crate) fn add_layout_constraints(
+    node: &StyleNode,
+    tree: &StyleTree,
+    variable_pool: &mut VariablePool,
+    solver: &mut Solver,
+) -> Result<(), StyleError> {
+    let (layout, layout_arith) = match (layout(node)?, property(node, "layout")) {
+        (Some(layout), Some(arith)) => (layout, arith),
+        _ => return Ok(()),
+    };
+    let (start, end, across) = match layout {
+        Layout::Stack => ("top", "bottom", "left"),
+        Layout::Row | Layout::Wrap => ("left", "right", "top"),
+    };
+    let gap = retrieve_variable(variable_pool, node.id, "gap");
+    let gap_arith = property(node, "gap").cloned().unwrap_or(Arith::Num(0.0));
+    let scope = Scope {
+        tree,
+        node,
+        attr_name: start,
+        bindings: &[],
+    };
+    let gap_value = arith_to_expression(&gap_arith, &scope, variable_pool)?;
+    add_constraint(
+        solver,
+        gap | WeightedRelation::EQ(REQUIRED) | gap_value,
+        || format!("gap = {}", gap_arith),
+    )?;
+    // Text is laid out as a paragraph at the top of the tag, so the children
+    // go below it
+    let top = Expression::from(retrieve_variable(variable_pool, node.id, "top"))
+        + match variable_pool
+            .get(&node.id)
+            .and_then(|attrs| attrs.get("intrinsic_height"))
+        {
+            Some(text_height) => *text_height + gap,
+            None => Expression::from_constant(0.0),
+        };
+    let mut origin = |attr_name| match attr_name {
+        "top" => top.clone(),
+        _ => Expression::from(retrieve_variable(variable_pool, node.id, attr_name)),
+    };
+    let (start_origin, across_origin) = (origin(start), origin(across));
+    let mut constraints = Vec::new();
+    let children = tag_children(tree, node);
+    for (index, child) in children.iter().enumerate() {
+        let mut variable = |id, attr_name| retrieve_variable(variable_pool, id, attr_name);
+        if layout == Layout::Wrap {
+            let flow_x = variable(child.id, "flow_x");
+            let flow_y = variable(child.id, "flow_y");
+            for flow in &[flow_x, flow_y] {
+                add_edit_variable(solver, *flow, 0.0)?;
+            }
+            constraints.extend_from_slice(&[
+                variable(child.id, "left")
+                    | WeightedRelation::EQ(LAYOUT_STRENGTH)
+                    | (variable(node.id, "left") + flow_x),
+                variable(child.id, "top")
+                    | WeightedRelation::EQ(LAYOUT_STRENGTH)
+                    | (top.clone() + flow_y),
+            ]);
+        } else {
+            let previous = match index {
+                0 => start_origin.clone(),
+                _ => variable(children[index - 1].id, end) + gap,
+            };
+            constraints.extend_from_slice(&[
+                variable(child.id, start) | WeightedRelation::EQ(LAYOUT_STRENGTH) | previous,
+                variable(child.id, across)
+                    | WeightedRelation::EQ(LAYOUT_STRENGTH)
+                    | across_origin.clone(),
+            ]);
+        }
+        for edge in &["right", "bottom"] {
+            constraints.push(
+                variable(node.id, edge)
+                    | WeightedRelation::GE(LAYOUT_STRENGTH)
+                    | variable(child.id, edge),
+            );
+        }
+    }
+    for constraint in constraints {
+        add_constraint(solver, constraint, || format!("layout = {}", layout_arith))?;
+    }
+    Ok(())
+}
+
+/// Breaks the children of every wrapping layout into rows at the solved
+/// sizes, until the positions settle. Returns how many passes moved a child.
+pub fn reflow_layout(
+    tree: &StyleTree,
+    variable_pool: &mut VariablePool,
+    solver: &mut Solver,
+) -> Result<usize, StyleError> {
+    let containers = tree
+        .iter()
+        .filter(|node| layout(node) == Ok(Some(Layout::Wrap)))
+        .collect::<Vec<_>>();
+    settle(|| {
+        let mut settled = true;
+        for node in &containers {
+            let mut value =
+                |id, attr_name| solver.get_value(retrieve_variable(variable_pool, id, attr_name));
+            let width = value(node.id, "width");
+            let gap = value(node.id, "gap");
+            let mut positions = Vec::new();
+            let (mut x, mut y, mut row_height) = (0.0, 0.0, 0.0_f64);
+            for child in tag_children(tree, node) {
+                let (child_width, child_height) =
+                    (value(child.id, "width"), value(child.id, "height"));
+                if x > 0.0 && x + child_width > width + REFLOW_TOLERANCE {
+                    x = 0.0;
+                    y += row_height + gap;
+                    row_height = 0.0;
+                }
+                positions.push((child.id, x, y));
+                x += child_width + gap;
+                row_height = row_height.max(child_height);
+            }
+            for (id, x, y) in positions {
+                for (attr_name, position) in &[("flow_x", x), ("flow_y", y)] {
+                    let flow = retrieve_variable(variable_pool, id, attr_name);
+                    if (solver.get_value(flow) - position).abs() > REFLOW_TOLERANCE {
+                        suggest_value(solver, flow, *position)?;
+                        settled = false;
+                    }
+                }
+            }
+        }
+        Ok(settled)
+    })
+}
+
+#[cfg(test)]
+mod layout_tests {
+    use super::*;
+    use crate::dom::fixture;
+    use crate::dom::style_tree::{solve_constraints, DefaultConstraints, NodeId};
+
+    /// Lays out `markup` in an 800x600 window and returns the left, top,
+    /// width and height of the tag with each id in `ids`.
+    fn boxes(sheet: &str, markup: &str, ids: &[usize]) -> Vec<(f64, f64, f64, f64)> {
+        let defaults = DefaultConstraints::none();
+        fixture::with_style(sheet, markup, &defaults, |tree, variable_pool, solver| {
+            solve_constraints(tree, variable_pool, solver)?;
+            reflow_layout(tree, variable_pool, solver)?;
+            Ok(ids
+                .iter()
+                .map(|id| {
+                    let mut value = |attr_name| {
+                        solver.get_value(retrieve_variable(variable_pool, NodeId(*id), attr_name))
+                    };
+                    (value("left"), value("top"), value("width"), value("height"))
+                })
+                .collect())
+        })
+        .unwrap()
+    }
+
+    const ITEMS: &str = "list { left = 10 top = 20 }
+        a { width = 30 height = 10 }
+        b { width = 40 height = 25 }";
+
+    #[test]
+    fn stack() {
+        let sheet = format!("{} list {{ layout = stack gap = 5 }}", ITEMS);
+        assert_eq!(
+            boxes(&sheet, "<list><a/>text<b/><a/></list>", &[0, 1, 3, 4]),
+            vec![
+                (10.0, 20.0, 40.0, 55.0),
+                (10.0, 20.0, 30.0, 10.0),
+                (10.0, 35.0, 40.0, 25.0),
+                (10.0, 65.0, 30.0, 10.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn row() {
+        let sheet = format!("{} list {{ layout = row gap = 1em }}", ITEMS);
+        assert_eq!(
+            boxes(&sheet, "<list><a/><b/></list>", &[0, 1, 2]),
+            vec![
+                (10.0, 20.0, 86.0, 25.0),
+                (10.0, 20.0, 30.0, 10.0),
+                (56.0, 20.0, 40.0, 25.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn overridden() {
+        // The stylesheet wins over the layout, which still places the
+        // siblings after it
+        let sheet = format!("{} list {{ layout = stack }} b {{ top = 100 }}", ITEMS);
+        assert_eq!(
+            boxes(&sheet, "<list><a/><b/><a/></list>", &[2, 3]),
+            vec![(10.0, 100.0, 40.0, 25.0), (10.0, 125.0, 30.0, 10.0)]
+        );
+    }
+
+    #[test]
+    fn wrap() {
+        let sheet = format!("{} list {{ layout = wrap gap = 10 width = 100 }}", ITEMS);
+        assert_eq!(
+            boxes(&sheet, "<list><a/><b/><a/><b/></list>", &[0, 1, 2, 3, 4]),
+            vec![
+                (10.0, 20.0, 100.0, 60.0),
+                (10.0, 20.0, 30.0, 10.0),
+                (50.0, 20.0, 40.0, 25.0),
+                (10.0, 55.0, 30.0, 10.0),
+                (50.0, 55.0, 40.0, 25.0),
+            ]
+        );
+    }
+
+    /// Solves `markup` styled by `sheet` without reflowing it.
+    fn solve(sheet: &str, markup: &str) -> Result<(), StyleError> {
+        fixture::with_style(
+            sheet,
+            markup,
+            &DefaultConstraints::none(),
+            solve_constraints,
+        )
+    }
+
+    #[test]
+    fn invalid_layout() {
+        assert_eq!(
+            solve("a { layout = grid }", "<a/>"),
+            Err(StyleError::InvalidValue("grid".to_string()))
+        );
+    }
+
+    #[test]
+    fn contradictory_gap() {
+        assert_eq!(
+            solve("a { layout = row gap = l[gap] + 1 }", "<a #l/>"),
+            Err(StyleError::Unsatisfiable("gap = $l[gap] + 1".to_string()))
+        );
+    }
+}
@@ -1,7 +1,31 @@
+pub(crate) mod error;
+#[cfg(test)]
+pub(crate) mod fixture;
+pub(crate) mod layout;
+pub(crate) mod property;
 pub(crate) mod style_tree;
 pub(crate) mod render_tree;
+pub(crate) mod text;
 
+pub use error::StyleError;
+pub use layout::reflow_layout;
 pub use style_tree::construct_style_tree;
+pub use style_tree::DefaultConstraints;
 pub use style_tree::generate_variable_pool;
+pub use style_tree::resize_window;
+pub use style_tree::retrieve_variable;
 pub use style_tree::solve_constraints;
-pub use render_tree::generate_render_tree;
\ No newline at end of file
+pub use style_tree::BOX_ATTRIBUTES;
+pub use style_tree::NodeId;
+pub use style_tree::PROPERTY_NAMES;
+pub use style_tree::StyleTree;
+pub use style_tree::VariablePool;
+pub use style_tree::WINDOW_ID;
+pub use render_tree::generate_render_tree;
+pub use render_tree::Rect;
+pub use property::PropertyValue;
+pub use property::INHERITED_PROPERTIES;
+pub use text::add_text_constraints;
+pub use text::reflow_text;
+pub use text::MeasureText;
+pub use text::TEXT_STRENGTH;
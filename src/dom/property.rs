@@ -0,0 +1,173 @@
+use super::error::StyleError;
+use super::style_tree::{arith_to_expression, Scope, VariablePool};
+use crate::parser::ass_parser::{Arith, Length, Unit};
+use crate::parser::Color;
+use cassowary::Solver;
+
+/// Properties a tag passes on to its children, and to its text, unless they
+/// set their own.
+pub const INHERITED_PROPERTIES: [&str; 1] = ["color"];
+
+/// The value of a property, such as `background_color` or `overflow`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum PropertyValue {
+    Color(Color),
+    /// In pixels
+    Length(f64),
+    Number(f64),
+    /// An argument that isn't an expression, kept verbatim
+    Str(String),
+    /// A bare word, such as `hidden`
+    Keyword(String),
+}
+
+/// Whether `arith` evaluates to pixels rather than to a plain number.
+fn is_length(arith: &Arith) -> bool {
+    match arith {
+        Arith::Ref(..) | Arith::Length(_) => true,
+        Arith::Add(a, b) | Arith::Sub(a, b) | Arith::Mul(a, b) | Arith::Div(a, b) => {
+            is_length(a) || is_length(b)
+        }
+        Arith::Neg(a) => is_length(a),
+        Arith::Num(_) | Arith::Color(_) | Arith::Ident(_) | Arith::Str(_) | Arith::Call(..) => {
+            false
+        }
+    }
+}
+
+/// Whether `arith` evaluates to a color. `color + 20%` and `color - 20%`
+/// lighten and darken `color`.
+fn is_color(arith: &Arith) -> bool {
+    match arith {
+        Arith::Color(_) => true,
+        Arith::Ident(name) => Color::named(name).is_some(),
+        Arith::Call(name, _) => color_function_arity(name).is_some(),
+        Arith::Add(a, _) | Arith::Sub(a, _) => is_color(a),
+        _ => false,
+    }
+}
+
+/// The number of arguments a function returning a color takes.
+fn color_function_arity(name: &str) -> Option<usize> {
+    match name {
+        "rgb" | "hsl" => Some(3),
+        "rgba" | "hsla" => Some(4),
+        "lighten" | "darken" => Some(2),
+        _ => None,
+    }
+}
+
+/// A number passed to a color function or given as an opacity, divided by
+/// `scale`. Percentages are always out of 100, so both `rgb(255, 0, 0)` and
+/// `rgb(100%, 0%, 0%)` are red.
+fn fraction(arith: &Arith, scale: f64) -> Result<f64, StyleError> {
+    match arith {
+        Arith::Num(n) => Ok(n / scale),
+        Arith::Length(Length {
+            value,
+            unit: Unit::Percent,
+        }) => Ok(value / 100.0),
+        Arith::Neg(a) => Ok(-fraction(a, scale)?),
+        _ => Err(StyleError::InvalidValue(arith.to_string())),
+    }
+}
+
+fn evaluate_color(arith: &Arith) -> Result<Color, StyleError> {
+    let invalid = || StyleError::InvalidValue(arith.to_string());
+    match arith {
+        Arith::Color(color) => Ok(*color),
+        Arith::Ident(name) => Color::named(name).ok_or_else(invalid),
+        Arith::Add(a, b) => Ok(evaluate_color(a)?.lighten(fraction(b, 100.0)?)),
+        Arith::Sub(a, b) => Ok(evaluate_color(a)?.darken(fraction(b, 100.0)?)),
+        Arith::Call(name, args) => {
+            let expected = color_function_arity(name).ok_or_else(invalid)?;
+            if args.len() != expected {
+                return Err(StyleError::Arity {
+                    name: name.clone(),
+                    expected,
+                    found: args.len(),
+                });
+            }
+            let alpha = args.get(3).map_or(Ok(1.0), |a| fraction(a, 1.0))?;
+            Ok(match name.as_str() {
+                "rgb" | "rgba" => Color::from_fractions(
+                    fraction(&args[0], 255.0)?,
+                    fraction(&args[1], 255.0)?,
+                    fraction(&args[2], 255.0)?,
+                    alpha,
+                ),
+                "hsl" | "hsla" => Color::from_hsla(
+                    fraction(&args[0], 1.0)?,
+                    fraction(&args[1], 100.0)?,
+                    fraction(&args[2], 100.0)?,
+                    alpha,
+                ),
+                "lighten" => evaluate_color(&args[0])?.lighten(fraction(&args[1], 100.0)?),
+                _ => evaluate_color(&args[0])?.darken(fraction(&args[1], 100.0)?),
+            })
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Evaluates the value of a property once the layout is solved. Expressions
+/// are evaluated like the right hand side of a constraint, so they can use
+/// units and refer to attributes.
+pub(crate) fn evaluate_property(
+    arith: &Arith,
+    scope: &Scope,
+    variable_pool: &mut VariablePool,
+    solver: &Solver,
+) -> Result<PropertyValue, StyleError> {
+    Ok(match arith {
+        _ if scope.attr_name == "opacity" => PropertyValue::Number(fraction(arith, 1.0)?),
+        _ if is_color(arith) => PropertyValue::Color(evaluate_color(arith)?),
+        Arith::Ident(keyword) => PropertyValue::Keyword(keyword.clone()),
+        Arith::Str(s) => PropertyValue::Str(s.clone()),
+        _ => {
+            let expression = arith_to_expression(arith, scope, variable_pool)?;
+            let value = expression.constant
+                + expression
+                    .terms
+                    .iter()
+                    .map(|term| term.coefficient * solver.get_value(term.variable))
+                    .sum::<f64>();
+            if is_length(arith) {
+                PropertyValue::Length(value)
+            } else {
+                PropertyValue::Number(value)
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod property_tests {
+    use super::*;
+    use crate::parser::ass_parser::ass_parser::value;
+
+    #[test]
+    fn color_errors() {
+        let color = |s| evaluate_color(&value(s).unwrap());
+        assert_eq!(
+            color("rgb(1, 2)"),
+            Err(StyleError::Arity {
+                name: "rgb".to_string(),
+                expected: 3,
+                found: 2
+            })
+        );
+        assert_eq!(
+            color("rgb(1, 2, $parent[width])"),
+            Err(StyleError::InvalidValue("$parent[width]".to_string()))
+        );
+        assert_eq!(
+            color("mix(red, blue)"),
+            Err(StyleError::InvalidValue("mix(red, blue)".to_string()))
+        );
+        assert_eq!(
+            color("nothing - 10%"),
+            Err(StyleError::InvalidValue("nothing".to_string()))
+        );
+    }
+}
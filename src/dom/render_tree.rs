@@ -1,55 +1,275 @@
-use super::style_tree::{retrieve_variable, StyleNode};
+use super::error::StyleError;
+use super::property::{evaluate_property, PropertyValue, INHERITED_PROPERTIES};
+use super::style_tree::{retrieve_variable, NodeId, Scope, StyleNode, StyleTree, VariablePool};
+use super::text::text_content;
 use crate::parser::asml_parser::Element;
-use cassowary::{AddConstraintError, Constraint, Solver, Variable, WeightedRelation};
+use cassowary::Solver;
 use std::collections::HashMap;
 
 #[derive(Debug, PartialEq)]
 pub struct RenderNode<'a> {
-    pub id: usize,
-    pub attrs: RenderData<'a>,
+    pub id: NodeId,
+    pub attrs: RenderData,
     pub children: Vec<RenderNode<'a>>,
     pub element: &'a Element,
 }
 
+/// A solved box, in pixels.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Rect {
+    pub left: f64,
+    pub top: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    pub fn right(&self) -> f64 {
+        self.left + self.width
+    }
+
+    pub fn bottom(&self) -> f64 {
+        self.top + self.height
+    }
+
+    /// The part of `self` inside `other`, which has no area if they don't
+    /// overlap.
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let left = self.left.max(other.left);
+        let top = self.top.max(other.top);
+        Rect {
+            left,
+            top,
+            width: (self.right().min(other.right()) - left).max(0.0),
+            height: (self.bottom().min(other.bottom()) - top).max(0.0),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
-pub struct RenderData<'a> {
-    pub constraints: HashMap<&'a String, f64>,
-    pub properties: HashMap<&'a String, &'a String>,
+pub struct RenderData {
+    pub rect: Rect,
+    pub constraints: HashMap<String, f64>,
+    pub properties: HashMap<String, PropertyValue>,
+    /// Whether children are clipped to `rect`, from `overflow = hidden`
+    pub clip: bool,
+    /// How opaque the node and its children are as a whole, between 0 and 1
+    pub opacity: f64,
+    pub font_size: f64,
+    /// The text in a tag, drawn as one paragraph at the top of `rect`
+    pub text: Option<String>,
 }
 
-pub fn generate_render_tree<'a>(
-    root: &'a StyleNode,
+fn render_node<'a>(
+    node: &StyleNode<'a>,
+    tree: &StyleTree<'a>,
+    parent_rect: Rect,
+    inherited: &HashMap<String, PropertyValue>,
     solver: &Solver,
-    variable_pool: &mut HashMap<usize, HashMap<&String, Variable>>,
-) -> RenderNode<'a> {
-    RenderNode {
-        id: root.id,
-        element: root.element,
-        children: root
-            .children
-            .iter()
-            .map(|child| {
-                return generate_render_tree(child, solver, variable_pool);
-            })
-            .collect(), // ::Vec<RenderNode>(),
-        attrs: RenderData {
-            constraints: root
-                .styles
-                .constraints
-                .iter()
-                .map(|(attr_name, _)| {
-                    (
-                        *attr_name,
-                        solver.get_value(retrieve_variable(variable_pool, root.id, attr_name)),
-                    )
+    variable_pool: &mut VariablePool,
+) -> Result<RenderNode<'a>, StyleError> {
+    let mut properties = inherited.clone();
+    let (rect, constraints) = match node.element {
+        Element::Tag { .. } => {
+            let mut value =
+                |attr_name| solver.get_value(retrieve_variable(variable_pool, node.id, attr_name));
+            let rect = Rect {
+                left: value("left"),
+                top: value("top"),
+                width: value("width"),
+                height: value("height"),
+            };
+            let constraints = variable_pool
+                .get(&node.id)
+                .map(|attr_to_variable| {
+                    attr_to_variable
+                        .iter()
+                        .map(|(attr_name, var)| (attr_name.clone(), solver.get_value(*var)))
+                        .collect()
                 })
-                .collect(),
-            properties: root
-                .styles
-                .properties
-                .iter()
-                .map(|(attr_name, _)| (*attr_name, *attr_name))
-                .collect(),
+                .unwrap_or_default();
+            // Later traits override earlier ones
+            for (attr_name, terms) in &node.styles.properties {
+                let value = match terms.first() {
+                    // Already evaluated, relative to the parent's font size
+                    _ if *attr_name == "font_size" => PropertyValue::Length(node.font_size),
+                    Some((_, arith, _)) => {
+                        let scope = Scope {
+                            tree,
+                            node,
+                            attr_name,
+                            bindings: &[],
+                        };
+                        evaluate_property(arith, &scope, variable_pool, solver)?
+                    }
+                    None => continue,
+                };
+                properties.insert(attr_name.to_string(), value);
+            }
+            (rect, constraints)
+        }
+        // A run of text is part of its tag's paragraph
+        Element::Text(_) => {
+            let height = variable_pool
+                .get(&node.parent_id())
+                .and_then(|attrs| attrs.get("intrinsic_height"))
+                .map_or(parent_rect.height, |height| solver.get_value(*height));
+            (
+                Rect {
+                    height,
+                    ..parent_rect
+                },
+                HashMap::new(),
+            )
+        }
+    };
+    let inherited = properties
+        .iter()
+        .filter(|(attr_name, _)| INHERITED_PROPERTIES.contains(&attr_name.as_str()))
+        .map(|(attr_name, value)| (attr_name.clone(), value.clone()))
+        .collect();
+    Ok(RenderNode {
+        id: node.id,
+        element: node.element,
+        children: tree
+            .children(node)
+            .map(|child| render_node(child, tree, rect, &inherited, solver, variable_pool))
+            .collect::<Result<_, _>>()?,
+        attrs: RenderData {
+            rect,
+            constraints,
+            clip: properties.get("overflow") == Some(&PropertyValue::Keyword("hidden".to_string())),
+            opacity: match properties.get("opacity") {
+                Some(PropertyValue::Number(opacity)) => opacity.clamp(0.0, 1.0),
+                _ => 1.0,
+            },
+            properties,
+            font_size: node.font_size,
+            text: text_content(tree, node),
         },
+    })
+}
+
+/// Reads the solved layout of every node and evaluates its properties. The
+/// text in a tag is laid out as one paragraph, as it is measured, so text
+/// nodes get the rect of that paragraph, along with the tag's inherited
+/// properties.
+pub fn generate_render_tree<'a>(
+    tree: &StyleTree<'a>,
+    solver: &Solver,
+    variable_pool: &mut VariablePool,
+) -> Result<RenderNode<'a>, StyleError> {
+    render_node(
+        tree.root(),
+        tree,
+        Rect::default(),
+        &HashMap::new(),
+        solver,
+        variable_pool,
+    )
+}
+
+#[cfg(test)]
+mod render_tree_tests {
+    use super::*;
+    use crate::dom::fixture;
+    use crate::dom::{solve_constraints, DefaultConstraints};
+    use crate::parser::Color;
+
+    /// The properties of every node, in document order.
+    fn properties(sheet: &str, markup: &str) -> Vec<HashMap<String, PropertyValue>> {
+        fn flatten(node: &RenderNode, out: &mut Vec<HashMap<String, PropertyValue>>) {
+            out.push(node.attrs.properties.clone());
+            node.children.iter().for_each(|child| flatten(child, out));
+        }
+        let defaults = DefaultConstraints::none();
+        fixture::with_style(sheet, markup, &defaults, |tree, variable_pool, solver| {
+            solve_constraints(tree, variable_pool, solver)?;
+            let render_tree = generate_render_tree(tree, solver, variable_pool)?;
+            let mut out = Vec::new();
+            flatten(&render_tree, &mut out);
+            Ok(out)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn typed_values() {
+        let nodes = properties(
+            "a { width = 200 font_size = 2em background_color = #ff0000 overflow = hidden }",
+            "<a/>",
+        );
+        assert_eq!(
+            nodes[0]["background_color"],
+            PropertyValue::Color(Color::from_u32(0xff0000))
+        );
+        assert_eq!(
+            nodes[0]["overflow"],
+            PropertyValue::Keyword("hidden".to_string())
+        );
+        assert_eq!(nodes[0]["font_size"], PropertyValue::Length(32.0));
+    }
+
+    #[test]
+    fn inherited_color() {
+        let nodes = properties(
+            "a { color = #00ff00 background_color = #0000ff } b { color = #ff0000 }",
+            "<a>text<b/><c/></a>",
+        );
+        assert_eq!(
+            nodes[1]["color"],
+            PropertyValue::Color(Color::from_u32(0x00ff00))
+        );
+        assert_eq!(
+            nodes[2]["color"],
+            PropertyValue::Color(Color::from_u32(0xff0000))
+        );
+        assert_eq!(
+            nodes[3]["color"],
+            PropertyValue::Color(Color::from_u32(0x00ff00))
+        );
+        assert!(!nodes[3].contains_key("background_color"));
+    }
+
+    #[test]
+    fn opacity() {
+        let nodes = properties(
+            "a { opacity = 50% } b { opacity = .25 }",
+            "<body><a/><b/></body>",
+        );
+        assert_eq!(nodes[1]["opacity"], PropertyValue::Number(0.5));
+        assert_eq!(nodes[2]["opacity"], PropertyValue::Number(0.25));
+        let defaults = DefaultConstraints::none();
+        let render = fixture::with_style(
+            "a { opacity = 10px }",
+            "<a/>",
+            &defaults,
+            |tree, variable_pool, solver| {
+                solve_constraints(tree, variable_pool, solver)?;
+                generate_render_tree(tree, solver, variable_pool).map(|_| ())
+            },
+        );
+        assert_eq!(render, Err(StyleError::InvalidValue("10px".to_string())));
+    }
+
+    #[test]
+    fn colors() {
+        let nodes = properties(
+            "a { color = orange background_color = rgba(255, 0, 0, 50%) }
+            b { color = hsl(120, 100%, 25%) background_color = #00f - 20% }
+            tag(color) { color = darken(color, 10%) background_color = color }",
+            "<body><a/><b/><tag(rgb(0, 0, 100%))/><tag(#fff8)/></body>",
+        );
+        let color = |i: usize, name: &str| match nodes[i][name] {
+            PropertyValue::Color(color) => color,
+            ref value => panic!("{:?} isn't a color", value),
+        };
+        assert_eq!(color(1, "color"), Color::from_u32(0xffa500));
+        assert_eq!(color(1, "background_color"), Color::rgba(255, 0, 0, 128));
+        assert_eq!(color(2, "color"), Color::from_u32(0x008000));
+        assert_eq!(color(2, "background_color"), Color::from_u32(0x000099));
+        assert_eq!(color(3, "color"), Color::from_u32(0x0000cc));
+        assert_eq!(color(3, "background_color"), Color::from_u32(0x0000ff));
+        assert_eq!(color(4, "color"), Color::rgba(0xe6, 0xe6, 0xe6, 0x88));
     }
 }
@@ -1,181 +1,1778 @@
+use super::error::StyleError;
+use super::layout::add_layout_constraints;
 use crate::parser::asml_parser::{Element, Trait};
-use crate::parser::ass_parser::{Arith, Entity, Relation, Style};
+use crate::parser::ass_parser::{
+    ass_parser::value, Alias, Arith, Entity, Length, Relation, Strength, Stylesheet, Term, Unit,
+};
 use cassowary::strength::{MEDIUM, REQUIRED, STRONG, WEAK};
-use cassowary::{AddConstraintError, Constraint, Solver, Variable, WeightedRelation};
+use cassowary::{AddConstraintError, Constraint, Expression, Solver, Variable, WeightedRelation};
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::ops::Index;
 
 #[derive(Debug, PartialEq)]
 pub struct StyleGroups<'a> {
-    pub constraints: Vec<(&'a String, &'a Vec<(Relation, Arith)>)>,
-    pub properties: Vec<(&'a String, &'a Vec<(Relation, Arith)>)>,
+    pub constraints: Vec<(&'a String, Vec<Term>)>,
+    pub properties: Vec<(&'a String, Vec<Term>)>,
+}
+
+/// Attributes every tag has in the variable pool, plus `center`, which
+/// stands for both `hcenter` and `vcenter`.
+pub const BOX_ATTRIBUTES: [&str; 11] = [
+    "left", "right", "top", "bottom", "width", "height", "x", "y", "hcenter", "vcenter", "center",
+];
+
+/// Variables the layouts and text measurement give some tags, which
+/// constraints may refer to but not set.
+const INTERNAL_ATTRIBUTES: [&str; 5] = [
+    "gap",
+    "flow_x",
+    "flow_y",
+    "intrinsic_width",
+    "intrinsic_height",
+];
+
+/// Properties understood by the renderer.
+pub const PROPERTY_NAMES: [&str; 19] = [
+    "background_color",
+    "border_bottom_left_radius",
+    "border_bottom_right_radius",
+    "border_color",
+    "border_radius",
+    "border_style",
+    "border_top_left_radius",
+    "border_top_right_radius",
+    "border_width",
+    "color",
+    "font_size",
+    "gap",
+    "layout",
+    "opacity",
+    "overflow",
+    "shadow_blur",
+    "shadow_color",
+    "shadow_x",
+    "shadow_y",
+];
+
+/// Index of a node in its `StyleTree`. Nodes are numbered in document
+/// order from 0 at the root, text included, so no two share an id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub usize);
+
+/// Key of the window in the variable pool, which `$window` refers to.
+pub const WINDOW_ID: NodeId = NodeId(usize::MAX);
+
+/// The variables of every node and of the window, by attribute name.
+pub type VariablePool = HashMap<NodeId, HashMap<String, Variable>>;
+
+/// Font size, in pixels, of elements that don't set one and don't inherit one.
+pub const DEFAULT_FONT_SIZE: f64 = 16.0;
+
+/// Largest font size, in pixels, a document may ask for. Glyphs are
+/// rasterized whole, so much larger sizes only exhaust memory.
+pub const MAX_FONT_SIZE: f64 = 4096.0;
+
+/// Constraints every tag starts out with, before those of its traits, as if
+/// each tag had a trait setting them. `$parent` of the root is the window,
+/// so what keeps a tag inside its parent keeps the root inside the window.
+///
+/// The default keeps tags inside their parent at `!medium`, and their width
+/// and height from going negative at `!strong`. The root also fills the
+/// window at `!weak`, so that an unconstrained root, and whatever is sized
+/// relative to it, has only one solution. Constraints in a stylesheet are
+/// required unless they say otherwise, so they still win, while a layout's
+/// are weaker and give way.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DefaultConstraints {
+    constraints: Vec<(String, Vec<Term>)>,
+    /// Added to the root only, after `constraints`
+    root: Vec<(String, Vec<Term>)>,
+}
+
+impl DefaultConstraints {
+    /// No default constraints at all.
+    pub fn none() -> DefaultConstraints {
+        DefaultConstraints {
+            constraints: Vec::new(),
+            root: Vec::new(),
+        }
+    }
+
+    /// Adds `attr_name rel arith` at `strength` to every tag.
+    pub fn with(
+        mut self,
+        attr_name: &str,
+        rel: Relation,
+        arith: Arith,
+        strength: Strength,
+    ) -> DefaultConstraints {
+        self.constraints
+            .push((attr_name.to_string(), vec![(rel, arith, Some(strength))]));
+        self
+    }
+
+    /// Keeps every edge of a tag inside the same edge of its parent.
+    pub fn containment(self, strength: Strength) -> DefaultConstraints {
+        let parent = |attr_name: &str| Arith::Ref(Entity::Parent, attr_name.to_string());
+        self.with("left", Relation::GE, parent("left"), strength.clone())
+            .with("top", Relation::GE, parent("top"), strength.clone())
+            .with("right", Relation::LE, parent("right"), strength.clone())
+            .with("bottom", Relation::LE, parent("bottom"), strength)
+    }
+
+    /// Keeps the width and height of a tag from going negative.
+    pub fn non_negative(self, strength: Strength) -> DefaultConstraints {
+        self.with("width", Relation::GE, Arith::Num(0.0), strength.clone())
+            .with("height", Relation::GE, Arith::Num(0.0), strength)
+    }
+
+    /// Makes the root as big as the window.
+    pub fn fill_window(mut self, strength: Strength) -> DefaultConstraints {
+        for attr_name in &["width", "height"] {
+            let window = Arith::Ref(Entity::Window, attr_name.to_string());
+            self.root.push((
+                attr_name.to_string(),
+                vec![(Relation::EQ, window, Some(strength.clone()))],
+            ));
+        }
+        self
+    }
+
+    /// The constraints to start a tag with.
+    fn for_tag(&self, root: bool) -> impl Iterator<Item = &(String, Vec<Term>)> {
+        let root = if root { &self.root[..] } else { &[] };
+        self.constraints.iter().chain(root)
+    }
+}
+
+impl Default for DefaultConstraints {
+    fn default() -> DefaultConstraints {
+        DefaultConstraints::none()
+            .containment(Strength::Medium)
+            .non_negative(Strength::Strong)
+            .fill_window(Strength::Weak)
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct StyleNode<'a> {
-    pub id: usize,
+    pub id: NodeId,
     pub element: &'a Element,
-    pub children: Vec<StyleNode<'a>>,
+    /// Names of every trait on the element, including those from aliases
+    pub traits: Vec<&'a String>,
+    /// `None` for the root
+    pub parent: Option<NodeId>,
+    pub children: Vec<NodeId>,
     pub styles: StyleGroups<'a>,
+    /// Font size in pixels, from the `font_size` property or inherited
+    pub font_size: f64,
+}
+
+impl<'a> StyleNode<'a> {
+    /// The node `$parent` refers to, which is the window for the root.
+    pub fn parent_id(&self) -> NodeId {
+        self.parent.unwrap_or(WINDOW_ID)
+    }
+
+    pub fn has_trait(&self, name: &str) -> bool {
+        self.traits.iter().any(|trait_| trait_.as_str() == name)
+    }
 }
 
-fn relation_to_operator(rel: &Relation) -> WeightedRelation {
+/// Every node of a document, stored in document order so that a node's id
+/// is its index.
+#[derive(Debug, PartialEq)]
+pub struct StyleTree<'a> {
+    nodes: Vec<StyleNode<'a>>,
+    /// Nodes by the `#id` given to them in the document
+    ids: HashMap<&'a str, NodeId>,
+}
+
+impl<'a> StyleTree<'a> {
+    pub fn root(&self) -> &StyleNode<'a> {
+        &self.nodes[0]
+    }
+
+    /// Every node, in document order.
+    pub fn iter(&self) -> impl Iterator<Item = &StyleNode<'a>> {
+        self.nodes.iter()
+    }
+
+    pub fn children<'b>(
+        &'b self,
+        node: &'b StyleNode<'a>,
+    ) -> impl Iterator<Item = &'b StyleNode<'a>> + 'b {
+        node.children.iter().map(move |id| &self[*id])
+    }
+
+    pub fn parent(&self, node: &StyleNode<'a>) -> Option<&StyleNode<'a>> {
+        node.parent.map(|id| &self[id])
+    }
+
+    /// The parent of `node`, its parent, and so on up to the root.
+    pub fn ancestors<'b>(
+        &'b self,
+        node: &'b StyleNode<'a>,
+    ) -> impl Iterator<Item = &'b StyleNode<'a>> + 'b {
+        std::iter::successors(self.parent(node), move |node| self.parent(node))
+    }
+
+    /// `node` and everything in it, in document order.
+    pub fn subtree<'b>(
+        &'b self,
+        node: &'b StyleNode<'a>,
+    ) -> impl Iterator<Item = &'b StyleNode<'a>> + 'b {
+        // A subtree is stored contiguously, and ends at the first node whose
+        // parent comes before `node`
+        self.nodes[node.id.0..]
+            .iter()
+            .take_while(move |other| other.id == node.id || other.parent >= Some(node.id))
+    }
+
+    /// Every tag sharing a parent with `node`, `node` included, in order.
+    pub fn siblings<'b>(
+        &'b self,
+        node: &'b StyleNode<'a>,
+    ) -> impl Iterator<Item = &'b StyleNode<'a>> + 'b {
+        self.parent(node)
+            .into_iter()
+            .flat_map(move |parent| self.children(parent))
+            .filter(|sibling| matches!(sibling.element, Element::Tag { .. }))
+    }
+
+    /// The node the document gave the id `name`.
+    pub fn get_by_id(&self, name: &str) -> Option<&StyleNode<'a>> {
+        self.ids.get(name).map(|id| &self[*id])
+    }
+}
+
+impl<'a> Index<NodeId> for StyleTree<'a> {
+    type Output = StyleNode<'a>;
+
+    fn index(&self, id: NodeId) -> &StyleNode<'a> {
+        &self.nodes[id.0]
+    }
+}
+
+/// Strength of the constraint at `index` in an `, else` chain, unless it has
+/// its own. The first is required and each alternative is weaker than the
+/// one before it.
+fn chain_strength(index: usize) -> f64 {
+    match index {
+        0 => REQUIRED,
+        1 => STRONG,
+        2 => MEDIUM,
+        _ => WEAK,
+    }
+}
+
+fn strength_to_cassowary(strength: &Strength) -> f64 {
+    match strength {
+        Strength::Required => REQUIRED,
+        Strength::Strong => STRONG,
+        Strength::Medium => MEDIUM,
+        Strength::Weak => WEAK,
+        Strength::Weight(weight) => weight.min(REQUIRED),
+    }
+}
+
+fn relation_to_operator(rel: &Relation, strength: f64) -> WeightedRelation {
     match rel {
-        Relation::EQ => WeightedRelation::EQ(REQUIRED),
-        Relation::GE => WeightedRelation::GE(REQUIRED),
-        Relation::LE => WeightedRelation::LE(REQUIRED),
+        Relation::EQ => WeightedRelation::EQ(strength),
+        Relation::GE => WeightedRelation::GE(strength),
+        Relation::LE => WeightedRelation::LE(strength),
     }
 }
 
+/// Returns the variable for `attr_name` of a node, creating it if nothing
+/// has referred to it yet.
 pub fn retrieve_variable(
-    variable_pool: &mut HashMap<usize, HashMap<&String, Variable>>,
-    node_id: usize,
-    attr_name: &String,
+    variable_pool: &mut VariablePool,
+    node_id: NodeId,
+    attr_name: &str,
 ) -> Variable {
-    match variable_pool.get(&node_id) {
-        Some(attr_to_variable) => match attr_to_variable.get(attr_name) {
-            Some(var) => *var,
-            None => panic!("Attribute Name {} Not Mapped in Variable Pool", attr_name),
-        },
-        None => panic!("Style Name Not Mapped in Variable Pool"),
+    *variable_pool
+        .entry(node_id)
+        .or_default()
+        .entry(attr_name.to_string())
+        .or_insert_with(Variable::new)
+}
+
+/// Replaces every parameter in `arith` with the value it is bound to in `env`.
+fn substitute(arith: &Arith, env: &HashMap<&String, Arith>) -> Arith {
+    match arith {
+        Arith::Ident(name) => env.get(name).cloned().unwrap_or_else(|| arith.clone()),
+        Arith::Add(a, b) => Arith::Add(Box::new(substitute(a, env)), Box::new(substitute(b, env))),
+        Arith::Sub(a, b) => Arith::Sub(Box::new(substitute(a, env)), Box::new(substitute(b, env))),
+        Arith::Mul(a, b) => Arith::Mul(Box::new(substitute(a, env)), Box::new(substitute(b, env))),
+        Arith::Div(a, b) => Arith::Div(Box::new(substitute(a, env)), Box::new(substitute(b, env))),
+        Arith::Neg(a) => Arith::Neg(Box::new(substitute(a, env))),
+        Arith::Call(name, args) => Arith::Call(
+            name.clone(),
+            args.iter().map(|arg| substitute(arg, env)).collect(),
+        ),
+        _ => arith.clone(),
+    }
+}
+
+/// Binds `params` to `args`, which must be as many.
+fn bind<'a>(
+    name: &str,
+    params: &'a [String],
+    args: &[Arith],
+) -> Result<HashMap<&'a String, Arith>, StyleError> {
+    if params.len() != args.len() {
+        return Err(StyleError::Arity {
+            name: name.to_string(),
+            expected: params.len(),
+            found: args.len(),
+        });
     }
+    Ok(params.iter().zip(args.iter().cloned()).collect())
+}
+
+/// Appends the trait `name(args)` to `expanded`, followed by the traits of
+/// the alias with its name (if any) with the alias' parameters substituted,
+/// recursively. `stack` holds the aliases currently being expanded so cycles
+/// can be reported instead of recursing forever.
+fn expand_aliases<'a>(
+    name: &'a String,
+    args: Vec<Arith>,
+    aliases: &'a [Alias],
+    stack: &mut Vec<&'a String>,
+    expanded: &mut Vec<(&'a String, Vec<Arith>)>,
+) -> Result<(), StyleError> {
+    expanded.push((name, args.clone()));
+    if let Some(alias) = aliases.iter().find(|alias| alias.from == *name) {
+        if let Some(start) = stack.iter().position(|name| **name == alias.from) {
+            let mut chain = stack[start..]
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<String>>();
+            chain.push(alias.from.clone());
+            return Err(StyleError::AliasCycle(chain));
+        }
+        let env = bind(name, &alias.params, &args)?;
+        stack.push(&alias.from);
+        for to in &alias.to {
+            let to_args = to.args.iter().map(|arg| substitute(arg, &env)).collect();
+            expand_aliases(&to.name, to_args, aliases, stack, expanded)?;
+        }
+        stack.pop();
+    }
+    Ok(())
+}
+
+/// Expands the aliases in `traits`, returning every trait that applies
+/// along with its arguments.
+fn expand_traits<'a>(
+    traits: &'a [Trait],
+    aliases: &'a [Alias],
+) -> Result<Vec<(&'a String, Vec<Arith>)>, StyleError> {
+    let mut expanded = vec![];
+    for trait_ in traits {
+        // Arguments in the markup are plain text; anything that isn't a valid
+        // value is passed along as a string
+        let args = trait_
+            .args
+            .iter()
+            .map(|arg| value(arg).unwrap_or_else(|_| Arith::Str(arg.clone())))
+            .collect();
+        expand_aliases(&trait_.name, args, aliases, &mut vec![], &mut expanded)?;
+    }
+    Ok(expanded)
 }
 
 fn collate_styles<'a>(
-    traits: &Vec<Trait>,
-    stylesheet: &'a Vec<Style>,
+    traits: &[(&'a String, Vec<Arith>)],
+    stylesheet: &'a Stylesheet,
     constraint_names: &'a HashSet<String>,
     property_names: &'a HashSet<String>,
-    default_attributes: &'a HashMap<String, Vec<(Relation, Arith)>>,
-) -> StyleGroups<'a> {
-    let mut constraints = vec![];
+    defaults: &'a DefaultConstraints,
+    root: bool,
+) -> Result<StyleGroups<'a>, StyleError> {
+    let mut constraints = defaults
+        .for_tag(root)
+        .map(|(attr_name, terms)| (attr_name, terms.clone()))
+        .collect::<Vec<_>>();
     let mut properties = vec![];
-    for trait_ in traits.iter() {
-        for style in stylesheet.iter() {
-            if trait_.name == style.name {
-                for attr in &style.attrs {
-                    if constraint_names.contains::<str>(&attr.0) {
-                        constraints.push(attr);
-                    } else if property_names.contains::<str>(&attr.0) {
-                        properties.push(attr);
+    for (name, args) in traits {
+        for style in stylesheet.styles.iter() {
+            if **name == style.name {
+                let env = bind(name, &style.params, args)?;
+                // Sorted by name so the solver sees constraints in the same
+                // order however a style lists them. The sort is stable, so a
+                // repeated attribute keeps its source order
+                let mut attrs = style.attrs.iter().collect::<Vec<_>>();
+                attrs.sort_by_key(|(attr_name, _)| attr_name);
+                for (attr_name, terms) in attrs {
+                    let terms = terms
+                        .iter()
+                        .map(|(rel, arith, strength)| {
+                            (rel.clone(), substitute(arith, &env), strength.clone())
+                        })
+                        .collect();
+                    if constraint_names.contains::<str>(attr_name) {
+                        constraints.push((attr_name, terms));
+                    } else if property_names.contains::<str>(attr_name) {
+                        properties.push((attr_name, terms));
                     }
                 }
-                for attr in default_attributes {
-                    constraints.push(attr);
-                }
             }
         }
     }
-    return StyleGroups {
+    Ok(StyleGroups {
         constraints,
         properties,
-    };
+    })
 }
 
-pub fn construct_style_tree<'a>(
-    root: &'a Element,
-    stylesheet: &'a Vec<Style>,
+/// Evaluates a `font_size` value to pixels. Relative sizes can't depend on
+/// the window, since font sizes are known before anything is solved.
+fn evaluate_font_size(arith: &Arith, parent: f64, root: f64) -> Result<f64, StyleError> {
+    Ok(match arith {
+        Arith::Num(n) => *n,
+        Arith::Length(Length { value, unit }) => match unit {
+            Unit::Px => *value,
+            Unit::Percent => value / 100.0 * parent,
+            Unit::Em => value * parent,
+            Unit::Rem => value * root,
+            Unit::Vw | Unit::Vh => {
+                return Err(StyleError::InvalidValue(
+                    Length {
+                        value: *value,
+                        unit: *unit,
+                    }
+                    .to_string(),
+                ))
+            }
+        },
+        Arith::Add(a, b) => {
+            evaluate_font_size(a, parent, root)? + evaluate_font_size(b, parent, root)?
+        }
+        Arith::Sub(a, b) => {
+            evaluate_font_size(a, parent, root)? - evaluate_font_size(b, parent, root)?
+        }
+        Arith::Mul(a, b) => {
+            evaluate_font_size(a, parent, root)? * evaluate_font_size(b, parent, root)?
+        }
+        Arith::Div(a, b) => {
+            let divisor = evaluate_font_size(b, parent, root)?;
+            if divisor == 0.0 {
+                return Err(StyleError::DivisionByZero(arith.to_string()));
+            }
+            evaluate_font_size(a, parent, root)? / divisor
+        }
+        Arith::Neg(a) => -evaluate_font_size(a, parent, root)?,
+        Arith::Ident(s) | Arith::Str(s) => return Err(StyleError::InvalidValue(s.clone())),
+        Arith::Color(_) | Arith::Call(..) => {
+            return Err(StyleError::InvalidValue(arith.to_string()))
+        }
+        Arith::Ref(_, attr_name) => return Err(StyleError::InvalidValue(attr_name.clone())),
+    })
+}
+
+/// Adds `element` and everything in it to `nodes`, returning its id.
+/// `root_font_size` is `None` when constructing the root itself.
+#[allow(clippy::too_many_arguments)]
+fn construct_node<'a>(
+    nodes: &mut Vec<StyleNode<'a>>,
+    element: &'a Element,
+    parent: Option<NodeId>,
+    stylesheet: &'a Stylesheet,
     constraint_names: &'a HashSet<String>,
     property_names: &'a HashSet<String>,
-    id: usize,
-    default_attributes: &'a HashMap<String, Vec<(Relation, Arith)>>,
-) -> StyleNode<'a> {
-    match root {
-        Element::Tag { traits, children } => {
-            // Loop through traits
-            return StyleNode {
-                id: id,
-                element: root,
-                children: children
-                    .iter()
-                    .enumerate()
-                    .map(|(i, child)| {
-                        construct_style_tree(
-                            child,
-                            stylesheet,
-                            constraint_names,
-                            property_names,
-                            id + 1 + i,
-                            default_attributes,
-                        )
-                    })
-                    .collect(),
-                styles: collate_styles(
-                    traits,
+    defaults: &'a DefaultConstraints,
+    parent_font_size: f64,
+    root_font_size: Option<f64>,
+) -> Result<NodeId, StyleError> {
+    let id = NodeId(nodes.len());
+    match element {
+        Element::Tag {
+            traits, children, ..
+        } => {
+            let traits = expand_traits(traits, &stylesheet.aliases)?;
+            let styles = collate_styles(
+                &traits,
+                stylesheet,
+                constraint_names,
+                property_names,
+                defaults,
+                parent.is_none(),
+            )?;
+            let font_size = match styles
+                .properties
+                .iter()
+                .rev()
+                .find(|(attr_name, _)| *attr_name == "font_size")
+                .and_then(|(_, terms)| terms.first())
+            {
+                Some((_, arith, _)) => {
+                    let font_size = evaluate_font_size(
+                        arith,
+                        parent_font_size,
+                        root_font_size.unwrap_or(DEFAULT_FONT_SIZE),
+                    )?;
+                    if !(font_size > 0.0 && font_size <= MAX_FONT_SIZE) {
+                        return Err(StyleError::InvalidValue(arith.to_string()));
+                    }
+                    font_size
+                }
+                None => parent_font_size,
+            };
+            nodes.push(StyleNode {
+                id,
+                element,
+                parent,
+                children: Vec::new(),
+                styles,
+                traits: traits.into_iter().map(|(name, _)| name).collect(),
+                font_size,
+            });
+            for child in children {
+                let child = construct_node(
+                    nodes,
+                    child,
+                    Some(id),
                     stylesheet,
                     constraint_names,
                     property_names,
-                    default_attributes,
-                ),
-            };
+                    defaults,
+                    font_size,
+                    Some(root_font_size.unwrap_or(font_size)),
+                )?;
+                nodes[id.0].children.push(child);
+            }
         }
-        Element::Text(_) => StyleNode {
-            id: id + 1,
-            element: root,
+        Element::Text(_) => nodes.push(StyleNode {
+            id,
+            element,
+            traits: vec![],
+            parent,
             children: vec![],
             styles: StyleGroups {
                 constraints: vec![],
                 properties: vec![],
             },
-        },
+            font_size: parent_font_size,
+        }),
     }
+    Ok(id)
 }
 
-pub fn generate_variable_pool<'a>(
-    root: &'a StyleNode,
-    stylesheet: &'a Vec<Style>,
+pub fn construct_style_tree<'a>(
+    root: &'a Element,
+    stylesheet: &'a Stylesheet,
     constraint_names: &'a HashSet<String>,
-    variable_pool: &mut HashMap<usize, HashMap<&'a String, Variable>>,
-) {
-    let id = root.id;
-    let mut attr_to_variable = HashMap::new();
-    for (attr_name, _) in &root.styles.constraints {
-        attr_to_variable.insert(*attr_name, Variable::new());
+    property_names: &'a HashSet<String>,
+    defaults: &'a DefaultConstraints,
+) -> Result<StyleTree<'a>, StyleError> {
+    let mut nodes = Vec::new();
+    construct_node(
+        &mut nodes,
+        root,
+        None,
+        stylesheet,
+        constraint_names,
+        property_names,
+        defaults,
+        DEFAULT_FONT_SIZE,
+        None,
+    )?;
+    let mut ids = HashMap::new();
+    for node in &nodes {
+        if let Element::Tag { id: Some(name), .. } = node.element {
+            if ids.insert(name.as_str(), node.id).is_some() {
+                return Err(StyleError::DuplicateId(name.clone()));
+            }
+        }
     }
-    variable_pool.insert(id, attr_to_variable);
-    for child in &root.children {
-        generate_variable_pool(child, stylesheet, constraint_names, variable_pool);
+    Ok(StyleTree { nodes, ids })
+}
+
+/// Strength pulling `left`, `top`, `width` and `height` towards 0, far below
+/// anything a stylesheet can ask for. Without it, what an underconstrained
+/// box solves to depends on the order constraints were added in.
+const DEFAULT_STRENGTH: f64 = WEAK / 1000.0;
+
+/// Creates the box model of a node: `left`, `top`, `width` and `height`,
+/// plus the attributes derived from them, tied together by required
+/// constraints. `x` and `y` are the same variables as `left` and `top`.
+fn add_box(id: NodeId, variable_pool: &mut VariablePool, solver: &mut Solver) {
+    let left = Variable::new();
+    let top = Variable::new();
+    let width = Variable::new();
+    let height = Variable::new();
+    let right = Variable::new();
+    let bottom = Variable::new();
+    let hcenter = Variable::new();
+    let vcenter = Variable::new();
+    solver
+        .add_constraints(&[
+            right | WeightedRelation::EQ(REQUIRED) | (left + width),
+            bottom | WeightedRelation::EQ(REQUIRED) | (top + height),
+            hcenter | WeightedRelation::EQ(REQUIRED) | (left + width * 0.5),
+            vcenter | WeightedRelation::EQ(REQUIRED) | (top + height * 0.5),
+            left | WeightedRelation::EQ(DEFAULT_STRENGTH) | 0.0,
+            top | WeightedRelation::EQ(DEFAULT_STRENGTH) | 0.0,
+            width | WeightedRelation::EQ(DEFAULT_STRENGTH) | 0.0,
+            height | WeightedRelation::EQ(DEFAULT_STRENGTH) | 0.0,
+        ])
+        .unwrap();
+    let attr_to_variable = variable_pool.entry(id).or_default();
+    for (attr_name, var) in &[
+        ("left", left),
+        ("x", left),
+        ("top", top),
+        ("y", top),
+        ("width", width),
+        ("height", height),
+        ("right", right),
+        ("bottom", bottom),
+        ("hcenter", hcenter),
+        ("vcenter", vcenter),
+    ] {
+        attr_to_variable.insert(attr_name.to_string(), *var);
     }
 }
 
-pub fn solve_constraints<'a>(
-    root: &'a StyleNode,
-    variable_pool: &mut HashMap<usize, HashMap<&String, Variable>>,
+/// Strength of edit variables, such as the window's size. Edit variables
+/// can't be required, so this is the strongest strength that isn't.
+pub(crate) const EDIT_STRENGTH: f64 = REQUIRED - 1.0;
+
+/// Makes `variable` an edit variable at `EDIT_STRENGTH` and suggests `value`
+/// for it.
+pub(crate) fn add_edit_variable(
+    solver: &mut Solver,
+    variable: Variable,
+    value: f64,
+) -> Result<(), StyleError> {
+    solver
+        .add_edit_variable(variable, EDIT_STRENGTH)
+        .map_err(|error| StyleError::Solver(format!("{:?}", error)))?;
+    suggest_value(solver, variable, value)
+}
+
+/// How many passes `settle` makes before giving up on a layout settling.
+const MAX_REFLOW_PASSES: usize = 8;
+
+/// Sizes and positions closer than this are considered settled.
+pub(crate) const REFLOW_TOLERANCE: f64 = 0.01;
+
+/// Repeats `pass` until it reports that nothing changed, since each change
+/// can move other boxes in turn. Returns how many passes changed something,
+/// which is at most a fixed limit in case the layout never settles.
+pub(crate) fn settle(
+    mut pass: impl FnMut() -> Result<bool, StyleError>,
+) -> Result<usize, StyleError> {
+    for passes in 0..MAX_REFLOW_PASSES {
+        if pass()? {
+            return Ok(passes);
+        }
+    }
+    Ok(MAX_REFLOW_PASSES)
+}
+
+/// Suggests a new `value` for the edit variable `variable`.
+pub(crate) fn suggest_value(
+    solver: &mut Solver,
+    variable: Variable,
+    value: f64,
+) -> Result<(), StyleError> {
+    solver
+        .suggest_value(variable, value)
+        .map_err(|error| StyleError::Solver(format!("{:?}", error)))
+}
+
+/// Creates the box model of the window and of every tag. The window's left
+/// and top edges are fixed at 0, and its width and height are edit variables
+/// set by `resize_window`. Variables for any other attribute are created when
+/// they are first referred to.
+pub fn generate_variable_pool(
+    tree: &StyleTree,
+    variable_pool: &mut VariablePool,
     solver: &mut Solver,
 ) {
-    let id = root.id;
-    for (attr_name, terms) in &root.styles.constraints {
-        for (rel, arith) in *terms {
-            let left_hand_variable = retrieve_variable(variable_pool, id, attr_name);
-            let constraint_operator = relation_to_operator(rel);
-            let new_constraint = match arith {
-                Arith::Num(n) => left_hand_variable | constraint_operator | *n as f64,
-                // Arith::Ref(entity, other_attr) => {
-                //     left_hand_variable
-                //         | constraint_operator
-                //         | match entity {
-                //             Entity::Other(other_style) => {
-                //                 retrieve_variable(variable_pool, id, &other_attr)
-                //             }
-                //             _ => panic!("STRANGE ENTITY"),
-                //         }
-                // }
-                _ => panic!("Invalid Expression"),
+    add_box(WINDOW_ID, variable_pool, solver);
+    let left = retrieve_variable(variable_pool, WINDOW_ID, "left");
+    let top = retrieve_variable(variable_pool, WINDOW_ID, "top");
+    solver
+        .add_constraints(&[
+            left | WeightedRelation::EQ(REQUIRED) | 0.0,
+            top | WeightedRelation::EQ(REQUIRED) | 0.0,
+        ])
+        .unwrap();
+    for attr_name in &["width", "height"] {
+        let variable = retrieve_variable(variable_pool, WINDOW_ID, attr_name);
+        solver.add_edit_variable(variable, EDIT_STRENGTH).unwrap();
+    }
+    for node in tree.iter() {
+        if let Element::Tag { .. } = node.element {
+            add_box(node.id, variable_pool, solver);
+        }
+    }
+}
+
+/// Suggests a new size for the window. Returns whether any variable changed
+/// since the solver's changes were last fetched, i.e. whether the render tree
+/// has to be regenerated.
+pub fn resize_window(
+    variable_pool: &mut VariablePool,
+    solver: &mut Solver,
+    width: f64,
+    height: f64,
+) -> Result<bool, StyleError> {
+    let window_width = retrieve_variable(variable_pool, WINDOW_ID, "width");
+    let window_height = retrieve_variable(variable_pool, WINDOW_ID, "height");
+    suggest_value(solver, window_width, width)?;
+    suggest_value(solver, window_height, height)?;
+    Ok(!solver.fetch_changes().is_empty())
+}
+
+/// `center` constrains both `hcenter` and `vcenter`.
+fn components(attr_name: &str) -> Vec<&str> {
+    match attr_name {
+        "center" => vec!["hcenter", "vcenter"],
+        _ => vec![attr_name],
+    }
+}
+
+fn is_vertical(attr_name: &str) -> bool {
+    matches!(attr_name, "top" | "bottom" | "height" | "vcenter" | "y")
+}
+
+/// Returns the id of the node `entity` refers to, as seen from the node of
+/// `scope`. A name is an element of an aggregate it is bound to, or else an
+/// id, or else a trait. A trait refers to the nearest ancestor carrying it,
+/// and failing that to the first node carrying it in the smallest subtree
+/// around `node` that has one, so the closest relative wins over a node in
+/// an unrelated part of the document.
+fn resolve_entity(entity: &Entity, scope: &Scope) -> Result<NodeId, StyleError> {
+    let (node, tree) = (scope.node, scope.tree);
+    let carrying = |sibling: &StyleNode, name: &Option<String>| {
+        name.as_deref().is_none_or(|name| sibling.has_trait(name))
+    };
+    let sibling = match entity {
+        Entity::Parent => return Ok(node.parent_id()),
+        Entity::Window => return Ok(WINDOW_ID),
+        Entity::Prev(name) => tree
+            .siblings(node)
+            .take_while(|sibling| sibling.id != node.id)
+            .filter(|sibling| carrying(sibling, name))
+            .last(),
+        Entity::Next(name) => tree
+            .siblings(node)
+            .skip_while(|sibling| sibling.id != node.id)
+            .skip(1)
+            .find(|sibling| carrying(sibling, name)),
+        Entity::Other(name) => {
+            if let Some((_, id)) = scope.bindings.iter().find(|(bound, _)| bound == name) {
+                return Ok(*id);
+            }
+            // An id names exactly one element, so it wins over a trait
+            return tree
+                .get_by_id(name)
+                .or_else(|| tree.ancestors(node).find(|node| node.has_trait(name)))
+                .or_else(|| {
+                    std::iter::once(node)
+                        .chain(tree.ancestors(node))
+                        .find_map(|around| {
+                            tree.subtree(around)
+                                .find(|other| other.id != node.id && other.has_trait(name))
+                        })
+                })
+                .map(|node| node.id)
+                .ok_or_else(|| StyleError::UnknownEntity(name.clone()));
+        }
+    };
+    sibling
+        .map(|sibling| sibling.id)
+        .ok_or_else(|| StyleError::NoSibling(entity.to_string()))
+}
+
+/// What an expression constraining `attr_name` of `node` can refer to.
+pub(crate) struct Scope<'b, 'a> {
+    pub(crate) tree: &'b StyleTree<'a>,
+    pub(crate) node: &'b StyleNode<'a>,
+    pub(crate) attr_name: &'b str,
+    /// Trait names standing for one particular element, inside `max(...)`
+    /// and `min(...)`
+    pub(crate) bindings: &'b [(&'b str, NodeId)],
+}
+
+/// Translates a length into pixels, or into a multiple of the width or
+/// height of the parent or window. `%` is along the axis of the attribute
+/// being constrained.
+fn length_to_expression(
+    length: &Length,
+    scope: &Scope,
+    variable_pool: &mut VariablePool,
+) -> Expression {
+    let Length { value, unit } = *length;
+    match unit {
+        Unit::Px => Expression::from_constant(value),
+        Unit::Em => Expression::from_constant(value * scope.node.font_size),
+        Unit::Rem => Expression::from_constant(value * scope.tree.root().font_size),
+        Unit::Percent => {
+            let dimension = if is_vertical(scope.attr_name) {
+                "height"
+            } else {
+                "width"
+            };
+            Expression::from(retrieve_variable(
+                variable_pool,
+                scope.node.parent_id(),
+                dimension,
+            )) * (value / 100.0)
+        }
+        Unit::Vw => {
+            Expression::from(retrieve_variable(variable_pool, WINDOW_ID, "width")) * (value / 100.0)
+        }
+        Unit::Vh => {
+            Expression::from(retrieve_variable(variable_pool, WINDOW_ID, "height"))
+                * (value / 100.0)
+        }
+    }
+}
+
+/// Translates `arith` into a linear expression over the variable pool.
+/// References to `[center]` mean `hcenter` or `vcenter`, whichever is along
+/// the axis of the attribute being constrained.
+pub(crate) fn arith_to_expression(
+    arith: &Arith,
+    scope: &Scope,
+    variable_pool: &mut VariablePool,
+) -> Result<Expression, StyleError> {
+    Ok(match arith {
+        Arith::Num(n) => Expression::from_constant(*n),
+        Arith::Length(length) => length_to_expression(length, scope, variable_pool),
+        Arith::Ref(entity, attr_name) => {
+            let internal = INTERNAL_ATTRIBUTES.contains(&attr_name.as_str());
+            if !BOX_ATTRIBUTES.contains(&attr_name.as_str()) && !internal {
+                return Err(StyleError::UnknownAttribute(arith.to_string()));
+            }
+            let target = resolve_entity(entity, scope)?;
+            // Otherwise it would be a free variable
+            if internal
+                && !variable_pool
+                    .get(&target)
+                    .is_some_and(|attrs| attrs.contains_key(attr_name))
+            {
+                return Err(StyleError::UnknownAttribute(arith.to_string()));
+            }
+            let attr_name = match attr_name.as_str() {
+                "center" if is_vertical(scope.attr_name) => "vcenter",
+                "center" => "hcenter",
+                _ => attr_name,
             };
-            println!("h{:#?}", new_constraint);
-            match solver.add_constraint(new_constraint) {
-                Ok(_) => println!("Constraint Added"),
-                Err(e) => match e {
-                    AddConstraintError::DuplicateConstraint => println!("Duplicate Constraint"),
-                    AddConstraintError::UnsatisfiableConstraint => {
-                        println!("Unsatisfiable Constraint")
+            Expression::from(retrieve_variable(variable_pool, target, attr_name))
+        }
+        Arith::Add(a, b) => {
+            arith_to_expression(a, scope, variable_pool)?
+                + arith_to_expression(b, scope, variable_pool)?
+        }
+        Arith::Sub(a, b) => {
+            arith_to_expression(a, scope, variable_pool)?
+                - arith_to_expression(b, scope, variable_pool)?
+        }
+        Arith::Mul(a, b) => {
+            let a = arith_to_expression(a, scope, variable_pool)?;
+            let b = arith_to_expression(b, scope, variable_pool)?;
+            match (a.terms.is_empty(), b.terms.is_empty()) {
+                (_, true) => a * b.constant,
+                (true, false) => b * a.constant,
+                (false, false) => return Err(StyleError::NonLinear(arith.to_string())),
+            }
+        }
+        Arith::Div(a, b) => {
+            let a = arith_to_expression(a, scope, variable_pool)?;
+            let b = arith_to_expression(b, scope, variable_pool)?;
+            if !b.terms.is_empty() {
+                return Err(StyleError::NonLinear(arith.to_string()));
+            }
+            if b.constant == 0.0 {
+                return Err(StyleError::DivisionByZero(arith.to_string()));
+            }
+            a / b.constant
+        }
+        Arith::Neg(a) => -arith_to_expression(a, scope, variable_pool)?,
+        Arith::Ident(s) | Arith::Str(s) => return Err(StyleError::InvalidValue(s.clone())),
+        Arith::Color(_) | Arith::Call(..) => {
+            return Err(StyleError::InvalidValue(arith.to_string()))
+        }
+    })
+}
+
+/// Adds the names of the traits `arith` refers to, other than ids, to
+/// `names`.
+fn referenced_traits<'b>(arith: &'b Arith, tree: &StyleTree, names: &mut Vec<&'b str>) {
+    match arith {
+        Arith::Ref(Entity::Other(name), _)
+            if tree.get_by_id(name).is_none() && !names.contains(&name.as_str()) =>
+        {
+            names.push(name)
+        }
+        Arith::Add(a, b) | Arith::Sub(a, b) | Arith::Mul(a, b) | Arith::Div(a, b) => {
+            referenced_traits(a, tree, names);
+            referenced_traits(b, tree, names);
+        }
+        Arith::Neg(a) => referenced_traits(a, tree, names),
+        Arith::Call(_, args) => args
+            .iter()
+            .for_each(|arg| referenced_traits(arg, tree, names)),
+        _ => {}
+    }
+}
+
+/// Every value the arguments of `max(...)` or `min(...)` take. An argument
+/// referring to a trait takes one value for each element carrying it, or for
+/// each combination of elements if it refers to several traits.
+fn aggregate_expressions(
+    args: &[Arith],
+    scope: &Scope,
+    variable_pool: &mut VariablePool,
+) -> Result<Vec<Expression>, StyleError> {
+    let mut expressions = Vec::new();
+    for arg in args {
+        let mut names = Vec::new();
+        referenced_traits(arg, scope.tree, &mut names);
+        let mut combinations = vec![Vec::new()];
+        for name in names {
+            let carriers = scope
+                .tree
+                .iter()
+                .filter(|node| node.has_trait(name))
+                .map(|node| node.id)
+                .collect::<Vec<_>>();
+            if carriers.is_empty() {
+                return Err(StyleError::UnknownEntity(name.to_string()));
+            }
+            combinations = combinations
+                .into_iter()
+                .flat_map(|bindings: Vec<(&str, NodeId)>| {
+                    carriers.iter().map(move |id| {
+                        let mut bindings = bindings.clone();
+                        bindings.push((name, *id));
+                        bindings
+                    })
+                })
+                .collect();
+        }
+        for bindings in &combinations {
+            let scope = Scope { bindings, ..*scope };
+            expressions.push(arith_to_expression(arg, &scope, variable_pool)?);
+        }
+    }
+    Ok(expressions)
+}
+
+/// Constrains `variable` by `max(...)` or `min(...)`, as one inequality per
+/// value. `= max(...)` is also weakly bounded by every value from above, which
+/// only the largest can satisfy, and `= min(...)` from below.
+fn aggregate_constraints(
+    variable: Variable,
+    term: (&Relation, &str, &[Arith]),
+    strength: f64,
+    scope: &Scope,
+    variable_pool: &mut VariablePool,
+) -> Result<Vec<Constraint>, StyleError> {
+    let (rel, function, args) = term;
+    let (bound, tight) = match (function, rel) {
+        ("max", Relation::GE) | ("min", Relation::LE) => (rel, None),
+        ("max", Relation::EQ) => (&Relation::GE, Some(&Relation::LE)),
+        ("min", Relation::EQ) => (&Relation::LE, Some(&Relation::GE)),
+        _ => {
+            let args = args.iter().map(Arith::to_string).collect::<Vec<_>>();
+            return Err(StyleError::Disjunction(format!(
+                "{} {} {}({})",
+                scope.attr_name,
+                rel,
+                function,
+                args.join(", ")
+            )));
+        }
+    };
+    let mut constraints = Vec::new();
+    for expression in aggregate_expressions(args, scope, variable_pool)? {
+        constraints.push(variable | relation_to_operator(bound, strength) | expression.clone());
+        if let Some(tight) = tight {
+            constraints.push(variable | relation_to_operator(tight, WEAK) | expression);
+        }
+    }
+    Ok(constraints)
+}
+
+/// Adds `constraint` to `solver`, describing it with `source` if it can't be
+/// satisfied.
+pub(crate) fn add_constraint(
+    solver: &mut Solver,
+    constraint: Constraint,
+    source: impl FnOnce() -> String,
+) -> Result<(), StyleError> {
+    match solver.add_constraint(constraint) {
+        // Adding the same constraint again changes nothing
+        Ok(()) | Err(AddConstraintError::DuplicateConstraint) => Ok(()),
+        Err(AddConstraintError::UnsatisfiableConstraint) => {
+            Err(StyleError::Unsatisfiable(source()))
+        }
+        Err(AddConstraintError::InternalSolverError(message)) => {
+            Err(StyleError::Solver(message.to_string()))
+        }
+    }
+}
+
+fn add_constraints(
+    node: &StyleNode,
+    tree: &StyleTree,
+    variable_pool: &mut VariablePool,
+    solver: &mut Solver,
+) -> Result<(), StyleError> {
+    let id = node.id;
+    for (attr_name, terms) in &node.styles.constraints {
+        for component in components(attr_name) {
+            // The first or last of a list of siblings falls back on an
+            // `, else` alternative, but there has to be one
+            let mut no_sibling = None;
+            let mut constrained = false;
+            for (index, (rel, arith, strength)) in terms.iter().enumerate() {
+                let left_hand_variable = retrieve_variable(variable_pool, id, component);
+                let strength = strength
+                    .as_ref()
+                    .map_or(chain_strength(index), strength_to_cassowary);
+                let constraint_operator = relation_to_operator(rel, strength);
+                let scope = Scope {
+                    tree,
+                    node,
+                    attr_name: component,
+                    bindings: &[],
+                };
+                let new_constraints = match arith {
+                    Arith::Call(function, args) if function == "max" || function == "min" => {
+                        aggregate_constraints(
+                            left_hand_variable,
+                            (rel, function, args),
+                            strength,
+                            &scope,
+                            variable_pool,
+                        )?
                     }
-                    AddConstraintError::InternalSolverError(s) => println!("{}", s),
-                },
+                    _ => match arith_to_expression(arith, &scope, variable_pool) {
+                        Ok(right_hand_side) => {
+                            vec![left_hand_variable | constraint_operator | right_hand_side]
+                        }
+                        Err(e @ StyleError::NoSibling(_)) => {
+                            no_sibling.get_or_insert(e);
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                    },
+                };
+                for new_constraint in new_constraints {
+                    add_constraint(solver, new_constraint, || {
+                        format!("{} {} {}", attr_name, rel, arith)
+                    })?;
+                }
+                constrained = true;
+            }
+            if let (Some(e), false) = (no_sibling, constrained) {
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Adds the constraints of every node to `solver`. `$parent` of the root is
+/// the window. Layouts go first, since they give tags the `gap` and `flow_*`
+/// variables constraints may refer to.
+pub fn solve_constraints(
+    tree: &StyleTree,
+    variable_pool: &mut VariablePool,
+    solver: &mut Solver,
+) -> Result<(), StyleError> {
+    tree.iter()
+        .try_for_each(|node| add_layout_constraints(node, tree, variable_pool, solver))?;
+    tree.iter()
+        .try_for_each(|node| add_constraints(node, tree, variable_pool, solver))
+}
+
+#[cfg(test)]
+mod style_tree_tests {
+    use super::*;
+    use crate::dom::fixture;
+    use crate::parser::asml_parser::asml_parser::tag;
+    use crate::parser::ass_parser::ass_parser::stylesheet;
+    use crate::parser::ass_parser::Entity;
+
+    fn names() -> (HashSet<String>, HashSet<String>) {
+        (
+            BOX_ATTRIBUTES.iter().map(|name| name.to_string()).collect(),
+            ["color".to_string(), "font_size".to_string()]
+                .iter()
+                .cloned()
+                .collect(),
+        )
+    }
+
+    fn attr_names(styles: &StyleGroups) -> Vec<String> {
+        let mut names = styles
+            .constraints
+            .iter()
+            .chain(styles.properties.iter())
+            .map(|(name, _)| name.to_string())
+            .collect::<Vec<String>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn alias_expansion() {
+        let sheet = stylesheet(
+            "par = wide colored
+            post = par
+            wide { width = 10 }
+            colored { color = 20 }
+            tall { height = 30 }",
+        )
+        .unwrap();
+        let body = tag("<post tall></post>").unwrap();
+        let (constraint_names, property_names) = names();
+        let defaults = DefaultConstraints::none();
+        let style_tree =
+            construct_style_tree(&body, &sheet, &constraint_names, &property_names, &defaults)
+                .unwrap();
+        assert_eq!(
+            attr_names(&style_tree.root().styles),
+            vec!["color", "height", "width"]
+        );
+    }
+
+    #[test]
+    fn alias_cycle() {
+        let sheet = stylesheet(
+            "a = b
+            b = c
+            c = a",
+        )
+        .unwrap();
+        let body = tag("<box><b/></box>").unwrap();
+        let (constraint_names, property_names) = names();
+        let defaults = DefaultConstraints::none();
+        let style_tree =
+            construct_style_tree(&body, &sheet, &constraint_names, &property_names, &defaults);
+        assert_eq!(
+            style_tree,
+            Err(StyleError::AliasCycle(vec![
+                "b".to_string(),
+                "c".to_string(),
+                "a".to_string(),
+                "b".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn alias_parameters() {
+        let sheet = stylesheet(
+            "tag(color) = bg(color) border(1 color)
+            bg(c) { color = c }
+            border(w c) { width = w height = $parent[height] - c }",
+        )
+        .unwrap();
+        let body = tag("<tag(7)/>").unwrap();
+        let (constraint_names, property_names) = names();
+        let defaults = DefaultConstraints::none();
+        let style_tree =
+            construct_style_tree(&body, &sheet, &constraint_names, &property_names, &defaults)
+                .unwrap();
+        let mut constraints = style_tree.root().styles.constraints.clone();
+        constraints.sort_by_key(|(name, _)| name.to_string());
+        assert_eq!(
+            constraints,
+            vec![
+                (
+                    &"height".to_string(),
+                    vec![(
+                        Relation::EQ,
+                        Arith::Sub(
+                            Box::new(Arith::Ref(Entity::Parent, "height".to_string())),
+                            Box::new(Arith::Num(7.0))
+                        ),
+                        None
+                    )]
+                ),
+                (
+                    &"width".to_string(),
+                    vec![(Relation::EQ, Arith::Num(1.0), None)]
+                ),
+            ]
+        );
+        assert_eq!(
+            style_tree.root().styles.properties,
+            vec![(
+                &"color".to_string(),
+                vec![(Relation::EQ, Arith::Num(7.0), None)]
+            )]
+        );
+    }
+
+    #[test]
+    fn alias_arity() {
+        let sheet = stylesheet(
+            "tag(color) = bg(color)
+            bg(c) { color = c }
+            plain = bold
+            bold { width = 1 }",
+        )
+        .unwrap();
+        let (constraint_names, property_names) = names();
+        let defaults = DefaultConstraints::none();
+        for (markup, name, expected, found) in &[
+            ("<tag/>", "tag", 1, 0),
+            ("<tag(1 2)/>", "tag", 1, 2),
+            ("<bg(1 2)/>", "bg", 1, 2),
+            ("<plain(1 2)/>", "plain", 0, 2),
+            ("<bold(1)/>", "bold", 0, 1),
+        ] {
+            let body = tag(markup).unwrap();
+            let style_tree =
+                construct_style_tree(&body, &sheet, &constraint_names, &property_names, &defaults);
+            assert_eq!(
+                style_tree,
+                Err(StyleError::Arity {
+                    name: name.to_string(),
+                    expected: *expected,
+                    found: *found,
+                })
+            );
+        }
+    }
+
+    /// Solves `markup` styled by `sheet` in an 800x600 window, without any
+    /// default constraints, returning the value of every variable in the pool
+    /// by node id and attribute name.
+    fn solve(sheet: &str, markup: &str) -> Result<HashMap<(usize, String), f64>, StyleError> {
+        solve_with(sheet, markup, &DefaultConstraints::none())
+    }
+
+    fn solve_with(
+        sheet: &str,
+        markup: &str,
+        defaults: &DefaultConstraints,
+    ) -> Result<HashMap<(usize, String), f64>, StyleError> {
+        fixture::with_style(sheet, markup, defaults, |tree, variable_pool, solver| {
+            solve_constraints(tree, variable_pool, solver)?;
+            Ok(variable_pool
+                .iter()
+                .flat_map(|(id, attrs)| {
+                    attrs
+                        .iter()
+                        .map(|(attr_name, var)| ((id.0, attr_name.clone()), solver.get_value(*var)))
+                        .collect::<Vec<_>>()
+                })
+                .collect())
+        })
+    }
+
+    #[test]
+    fn parent_reference() {
+        let values = solve(
+            "outer { width = 100 }
+            inner { width = $parent[width] - 20 }",
+            "<outer><inner/></outer>",
+        )
+        .unwrap();
+        assert_eq!(values[&(1, "width".to_string())], 80.0);
+    }
+
+    #[test]
+    fn window_reference() {
+        let values = solve(
+            "outer { height = $window[height] width = $window[width] - $window[height] }",
+            "<outer></outer>",
+        )
+        .unwrap();
+        assert_eq!(values[&(0, "height".to_string())], 600.0);
+        assert_eq!(values[&(0, "width".to_string())], 200.0);
+    }
+
+    #[test]
+    fn root_parent_is_window() {
+        let values = solve("outer { width = $parent[width] }", "<outer/>").unwrap();
+        assert_eq!(values[&(0, "width".to_string())], 800.0);
+    }
+
+    #[test]
+    fn trait_reference() {
+        let values = solve(
+            "a { width = 30 }
+            b { width = a[width] + 5 height = a[height] }
+            c = a
+            d { height = c[width] }",
+            "<box><c/><b/><d/></box>",
+        )
+        .unwrap();
+        assert_eq!(values[&(2, "width".to_string())], 35.0);
+        assert_eq!(values[&(3, "height".to_string())], 30.0);
+    }
+
+    #[test]
+    fn unknown_entity() {
+        assert_eq!(
+            solve("b { width = a[width] }", "<box><b/></box>"),
+            Err(StyleError::UnknownEntity("a".to_string()))
+        );
+    }
+
+    #[test]
+    fn unknown_attribute() {
+        assert_eq!(
+            solve("b { width = $parent[widht] }", "<box><b/></box>"),
+            Err(StyleError::UnknownAttribute("$parent[widht]".to_string()))
+        );
+        let values = solve(
+            "box { width = 200 layout = stack gap = 10 } b { width = $parent[width] - $parent[gap] }",
+            "<box><b/></box>",
+        )
+        .unwrap();
+        assert_eq!(values[&(1, "width".to_string())], 190.0);
+        // Only tags with text are measured
+        assert_eq!(
+            solve(
+                "box { width = 200 } b { width = $parent[width] - $parent[intrinsic_width] }",
+                "<box><b/></box>"
+            ),
+            Err(StyleError::UnknownAttribute(
+                "$parent[intrinsic_width]".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn id_reference() {
+        let values = solve(
+            "post { top = 10 height = 20 }
+            a { top = first[bottom] }
+            b { top = $second[bottom] + 5 }",
+            "<box><post #first/><post #second/><a/><b/></box>",
+        )
+        .unwrap();
+        assert_eq!(values[&(3, "top".to_string())], 30.0);
+        assert_eq!(values[&(4, "top".to_string())], 35.0);
+    }
+
+    #[test]
+    fn id_before_trait() {
+        // `a` is both a trait of the first child and the id of the second
+        let values = solve(
+            "a { width = 10 }
+            wide { width = 30 }
+            b { width = $a[width] }",
+            "<box><a/><wide #a/><b/></box>",
+        )
+        .unwrap();
+        assert_eq!(values[&(3, "width".to_string())], 30.0);
+    }
+
+    #[test]
+    fn duplicate_id() {
+        assert_eq!(
+            solve("", "<box><a #one/><b><c #one/></b></box>"),
+            Err(StyleError::DuplicateId("one".to_string()))
+        );
+    }
+
+    #[test]
+    fn nearest_ancestor() {
+        let values = solve(
+            "big { width = 300 }
+            small { width = 100 }
+            a { width = panel[width] }",
+            "<panel big><panel small><a/></panel><a/></panel>",
+        )
+        .unwrap();
+        assert_eq!(values[&(2, "width".to_string())], 100.0);
+        assert_eq!(values[&(3, "width".to_string())], 300.0);
+    }
+
+    #[test]
+    fn nearest_relative() {
+        // Each title finds the header of its own card, not the first one in
+        // the document
+        let values = solve(
+            "header { top = 0 }
+            small { height = 10 }
+            big { height = 30 }
+            title { top = header[bottom] }",
+            "<box><card><header small/><title/></card><card><header big/><title/></card></box>",
+        )
+        .unwrap();
+        assert_eq!(values[&(3, "top".to_string())], 10.0);
+        assert_eq!(values[&(6, "top".to_string())], 30.0);
+        // Failing that, the first one in the document
+        let values = solve(
+            "header { top = 0 }
+            small { height = 10 }
+            big { height = 30 }
+            title { top = header[bottom] }",
+            "<box><card><header small/><header big/></card><title/></box>",
+        )
+        .unwrap();
+        assert_eq!(values[&(4, "top".to_string())], 10.0);
+        // A node carrying the trait itself refers to another carrier
+        let values = solve(
+            "wide { width = 50 }
+            narrow { width = 20 }
+            card { height = card[width] }",
+            "<box><card wide/><card narrow/></box>",
+        )
+        .unwrap();
+        assert_eq!(values[&(1, "height".to_string())], 20.0);
+        assert_eq!(values[&(2, "height".to_string())], 50.0);
+    }
+
+    #[test]
+    fn siblings() {
+        let values = solve(
+            "item { height = 10 top = $prev[bottom] + 5, else >= $parent[top] }
+            list { top = 0 }
+            last { width = $prev(mark)[width] + $next[width], else = $prev(mark)[width] }
+            mark { width = 40 }",
+            "<list><item mark/>text<item/><item last/></list>",
+        )
+        .unwrap();
+        assert_eq!(values[&(1, "top".to_string())], 0.0);
+        // Text isn't a sibling
+        assert_eq!(values[&(3, "top".to_string())], 15.0);
+        assert_eq!(values[&(4, "top".to_string())], 30.0);
+        // Without a next sibling, the first constraint is dropped as a whole
+        assert_eq!(values[&(4, "width".to_string())], 40.0);
+        // Without an alternative, the missing sibling is an error
+        assert_eq!(
+            solve("item { top = $prev[bottom] }", "<list><item/><item/></list>"),
+            Err(StyleError::NoSibling("$prev".to_string()))
+        );
+    }
+
+    #[test]
+    fn aggregates() {
+        let values = solve(
+            "cell { left = 0 }
+            narrow { width = 10 }
+            wide { width = 30 }
+            total {
+                width = max(cell[width])
+                height = min(cell[width], 20)
+                left >= max(cell[right], 5)
+            }",
+            "<box><cell narrow/><cell wide/><total/></box>",
+        )
+        .unwrap();
+        assert_eq!(values[&(3, "width".to_string())], 30.0);
+        assert_eq!(values[&(3, "height".to_string())], 10.0);
+        assert_eq!(values[&(3, "left".to_string())], 30.0);
+        assert_eq!(
+            solve("a { width <= max(cell[width]) }", "<box><cell/><a/></box>"),
+            Err(StyleError::Disjunction(
+                "width <= max($cell[width])".to_string()
+            ))
+        );
+        assert_eq!(
+            solve("a { width = max(cell[width]) }", "<a/>"),
+            Err(StyleError::UnknownEntity("cell".to_string()))
+        );
+    }
+
+    #[test]
+    fn containment() {
+        let sheet = "outer { left = 100 top = 0 width = 100 height = 100 }
+            a { width = 50 hcenter = $parent[left] !weak }
+            b { left = $parent[left] - 10 }
+            c { width = $parent[width] - 200 !medium }";
+        let markup = "<outer><a/><b/><c/></outer>";
+        let contained = solve_with(sheet, markup, &DefaultConstraints::default()).unwrap();
+        assert_eq!(contained[&(1, "left".to_string())], 100.0);
+        // Required constraints win
+        assert_eq!(contained[&(2, "left".to_string())], 90.0);
+        assert_eq!(contained[&(3, "width".to_string())], 0.0);
+        let free = solve(sheet, markup).unwrap();
+        assert_eq!(free[&(1, "left".to_string())], 75.0);
+        assert_eq!(free[&(3, "width".to_string())], -100.0);
+    }
+
+    #[test]
+    fn root_in_window() {
+        let values = solve_with(
+            "a { width = 100 left = 750 !weak }",
+            "<a/>",
+            &DefaultConstraints::default(),
+        )
+        .unwrap();
+        assert_eq!(values[&(0, "left".to_string())], 700.0);
+        let values = solve_with(
+            "b { width = 10 height = 10 }",
+            "<a><b/></a>",
+            &DefaultConstraints::default(),
+        )
+        .unwrap();
+        let value = |attr_name: &str| values[&(0, attr_name.to_string())];
+        assert_eq!(
+            (value("left"), value("top"), value("width"), value("height")),
+            (0.0, 0.0, 800.0, 600.0)
+        );
+    }
+
+    #[test]
+    fn custom_defaults() {
+        let defaults = DefaultConstraints::none().with(
+            "height",
+            Relation::EQ,
+            Arith::Length(Length {
+                value: 2.0,
+                unit: Unit::Em,
+            }),
+            Strength::Weak,
+        );
+        let values = solve_with("b { height = 10 }", "<a><b/></a>", &defaults).unwrap();
+        assert_eq!(values[&(0, "height".to_string())], 32.0);
+        assert_eq!(values[&(1, "height".to_string())], 10.0);
+    }
+
+    #[test]
+    fn invalid_value() {
+        assert_eq!(
+            solve("b { width = wide }", "<b/>"),
+            Err(StyleError::InvalidValue("wide".to_string()))
+        );
+    }
+
+    #[test]
+    fn derived_attributes() {
+        let values = solve("box { left = 10 width = 100 y = 5 height = 50 }", "<box/>").unwrap();
+        let value = |attr_name: &str| values[&(0, attr_name.to_string())];
+        assert_eq!(value("right"), 110.0);
+        assert_eq!(value("x"), 10.0);
+        assert_eq!(value("top"), 5.0);
+        assert_eq!(value("bottom"), 55.0);
+        assert_eq!(value("hcenter"), 60.0);
+        assert_eq!(value("vcenter"), 30.0);
+    }
+
+    #[test]
+    fn centering() {
+        let values = solve(
+            "a { width = 200 hcenter = $window[hcenter] }
+            b { width = 100 height = 50 center = $window[center] }
+            c { height = 100 vcenter = $window[center] }",
+            "<box><a/><b/><c/></box>",
+        )
+        .unwrap();
+        assert_eq!(values[&(1, "left".to_string())], 300.0);
+        assert_eq!(values[&(2, "left".to_string())], 350.0);
+        assert_eq!(values[&(2, "top".to_string())], 275.0);
+        assert_eq!(values[&(3, "top".to_string())], 250.0);
+    }
+
+    #[test]
+    fn else_chain() {
+        let values = solve(
+            "a { width <= 90, else = 100 }
+            b { width <= $window[width], else = 1000 }
+            c { width <= 300, else = 100, else = 200 }
+            d { width >= 500, else = 100, else = 200, else = 300, else = 400 }",
+            "<box><a/><b/><c/><d/></box>",
+        )
+        .unwrap();
+        assert_eq!(values[&(1, "width".to_string())], 90.0);
+        assert_eq!(values[&(2, "width".to_string())], 800.0);
+        assert_eq!(values[&(3, "width".to_string())], 100.0);
+        assert_eq!(values[&(4, "width".to_string())], 500.0);
+    }
+
+    #[test]
+    fn strength_annotations() {
+        let values = solve(
+            "wide { width = 300 !strong }
+            narrow { width = 100 !weak }
+            tall { height = 300 !500 }
+            short { height = 100 !medium }
+            far { left <= 100, else = 80 !required, else = 90 }",
+            "<box><a wide narrow/><b tall short/><c far/></box>",
+        )
+        .unwrap();
+        assert_eq!(values[&(1, "width".to_string())], 300.0);
+        assert_eq!(values[&(2, "height".to_string())], 100.0);
+        assert_eq!(values[&(3, "left".to_string())], 80.0);
+    }
+
+    #[test]
+    fn units() {
+        let values = solve(
+            "outer { width = 50vw height = 25vh font_size = 20px }
+            a { width = 50% height = 10% left = 2em top = 1rem }
+            b { font_size = 1.5em width = 2em height = 3rem }
+            c { font_size = 50% left = 10px + 1em }",
+            "<outer><a/><b/><c/></outer>",
+        )
+        .unwrap();
+        let value = |id, attr_name: &str| values[&(id, attr_name.to_string())];
+        assert_eq!(value(0, "width"), 400.0);
+        assert_eq!(value(0, "height"), 150.0);
+        assert_eq!(value(1, "width"), 200.0);
+        assert_eq!(value(1, "height"), 15.0);
+        assert_eq!(value(1, "left"), 40.0);
+        assert_eq!(value(1, "top"), 20.0);
+        assert_eq!(value(2, "width"), 60.0);
+        assert_eq!(value(2, "height"), 60.0);
+        assert_eq!(value(3, "left"), 20.0);
+    }
+
+    #[test]
+    fn window_relative_font_size() {
+        assert_eq!(
+            solve("a { font_size = 2vw }", "<a/>"),
+            Err(StyleError::InvalidValue("2vw".to_string()))
+        );
+    }
+
+    #[test]
+    fn font_size_bounds() {
+        assert_eq!(
+            solve("a { font_size = -5 }", "<a/>"),
+            Err(StyleError::InvalidValue("-5".to_string()))
+        );
+        assert_eq!(
+            solve("a { font_size = 0px }", "<a/>"),
+            Err(StyleError::InvalidValue("0px".to_string()))
+        );
+        assert_eq!(
+            solve("a { font_size = 100000 }", "<a/>"),
+            Err(StyleError::InvalidValue("100000".to_string()))
+        );
+        assert!(solve("a { font_size = 20 - 4 }", "<a/>").is_ok());
+    }
+
+    #[test]
+    fn arithmetic() {
+        let values = solve(
+            "a { width = ($window[width] - 100) / 2 height = -10 + 2 * 50% left = 1.5 * $parent[left] + 3 }",
+            "<a/>",
+        )
+        .unwrap();
+        assert_eq!(values[&(0, "width".to_string())], 350.0);
+        assert_eq!(values[&(0, "height".to_string())], 590.0);
+        assert_eq!(values[&(0, "left".to_string())], 3.0);
+    }
+
+    #[test]
+    fn non_linear() {
+        assert_eq!(
+            solve("a { width = $parent[width] * $parent[height] }", "<a/>"),
+            Err(StyleError::NonLinear(
+                "$parent[width] * $parent[height]".to_string()
+            ))
+        );
+        assert_eq!(
+            solve("a { width = 100 / (5 - 5) }", "<a/>"),
+            Err(StyleError::DivisionByZero("100 / (5 - 5)".to_string()))
+        );
+    }
+
+    #[test]
+    fn unsatisfiable() {
+        assert_eq!(
+            solve("a { width = 10 width = 20 }", "<a/>"),
+            Err(StyleError::Unsatisfiable("width = 20".to_string()))
+        );
+        assert_eq!(
+            solve("a { width = 10 } b { width = 20 }", "<a b/>"),
+            Err(StyleError::Unsatisfiable("width = 20".to_string()))
+        );
+        let values = solve("a { width >= 10 width <= 20 width = 30 !weak }", "<a/>").unwrap();
+        assert_eq!(values[&(0, "width".to_string())], 20.0);
+    }
+
+    #[test]
+    fn resize() {
+        let sheet = stylesheet("a { width = 50vw height = $window[height] - 100 }").unwrap();
+        let body = tag("<a/>").unwrap();
+        let (constraint_names, property_names) = names();
+        let defaults = DefaultConstraints::none();
+        let style_tree =
+            construct_style_tree(&body, &sheet, &constraint_names, &property_names, &defaults)
+                .unwrap();
+        let mut solver = Solver::new();
+        let mut variable_pool = HashMap::new();
+        generate_variable_pool(&style_tree, &mut variable_pool, &mut solver);
+        solve_constraints(&style_tree, &mut variable_pool, &mut solver).unwrap();
+        assert!(resize_window(&mut variable_pool, &mut solver, 800.0, 600.0).unwrap());
+        assert!(!resize_window(&mut variable_pool, &mut solver, 800.0, 600.0).unwrap());
+        assert!(resize_window(&mut variable_pool, &mut solver, 1000.0, 300.0).unwrap());
+        let width = retrieve_variable(&mut variable_pool, NodeId(0), "width");
+        let height = retrieve_variable(&mut variable_pool, NodeId(0), "height");
+        assert_eq!(solver.get_value(width), 500.0);
+        assert_eq!(solver.get_value(height), 200.0);
+    }
+
+    #[test]
+    fn unique_ids() {
+        let sheet = stylesheet("").unwrap();
+        let body = tag("<a><b><c/><c/></b><d>text</d><e><f><g/></f></e></a>").unwrap();
+        let (constraint_names, property_names) = names();
+        let defaults = DefaultConstraints::none();
+        let style_tree =
+            construct_style_tree(&body, &sheet, &constraint_names, &property_names, &defaults)
+                .unwrap();
+        let ids = style_tree.iter().map(|node| node.id.0).collect::<Vec<_>>();
+        assert_eq!(ids, (0..ids.len()).collect::<Vec<_>>());
+        let names = style_tree
+            .iter()
+            .map(|node| match node.element {
+                Element::Tag { traits, .. } => traits[0].name.as_str(),
+                Element::Text(_) => "text",
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a", "b", "c", "c", "d", "text", "e", "f", "g"]);
+        for node in style_tree.iter() {
+            for child in style_tree.children(node) {
+                assert_eq!(child.parent, Some(node.id));
             }
         }
+        assert_eq!(
+            style_tree.parent(&style_tree[NodeId(8)]).unwrap().id,
+            NodeId(7)
+        );
+        assert_eq!(style_tree.root().parent_id(), WINDOW_ID);
+
+        let mut solver = Solver::new();
+        let mut variable_pool = HashMap::new();
+        generate_variable_pool(&style_tree, &mut variable_pool, &mut solver);
+        // Every tag and the window get a box, and text doesn't
+        let mut keys = variable_pool.keys().cloned().collect::<Vec<_>>();
+        keys.sort();
+        let mut expected = style_tree
+            .iter()
+            .filter(|node| matches!(node.element, Element::Tag { .. }))
+            .map(|node| node.id)
+            .collect::<Vec<_>>();
+        expected.push(WINDOW_ID);
+        assert_eq!(keys, expected);
     }
-    for child in &root.children {
-        solve_constraints(child, variable_pool, solver);
+
+    #[test]
+    fn siblings_and_nephews() {
+        // With ids derived from the parent's, `c` and `d` used to share
+        // variables
+        let values = solve(
+            "b { width = 10 }
+            c { width = 20 }
+            d { width = 30 }",
+            "<a><b><c/></b><d/></a>",
+        )
+        .unwrap();
+        assert_eq!(values[&(1, "width".to_string())], 10.0);
+        assert_eq!(values[&(2, "width".to_string())], 20.0);
+        assert_eq!(values[&(3, "width".to_string())], 30.0);
     }
 }
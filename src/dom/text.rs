@@ -0,0 +1,170 @@
+use super::error::StyleError;
+use super::style_tree::{
+    retrieve_variable, settle, suggest_value, StyleNode, StyleTree, VariablePool, EDIT_STRENGTH,
+    REFLOW_TOLERANCE,
+};
+use crate::parser::asml_parser::Element;
+use cassowary::strength::{STRONG, WEAK};
+use cassowary::{Solver, WeightedRelation};
+
+/// Default strength of `height >= intrinsic_height`. Strong enough to beat
+/// `, else` alternatives after the first, but not a required height.
+pub const TEXT_STRENGTH: f64 = STRONG;
+
+/// Something that knows how big text is once laid out, such as a font.
+pub trait MeasureText {
+    /// Returns the width and height of `content` at a font size of `px`,
+    /// wrapped at `max_width` if given.
+    fn measure_text(&self, content: &str, px: f64, max_width: Option<f64>) -> (f64, f64);
+}
+
+/// The text children of a tag, laid out as a single run.
+pub(crate) fn text_content(tree: &StyleTree, node: &StyleNode) -> Option<String> {
+    let runs = tree
+        .children(node)
+        .filter_map(|child| match child.element {
+            Element::Text(content) => Some(content.as_str()),
+            Element::Tag { .. } => None,
+        })
+        .collect::<Vec<&str>>();
+    if runs.is_empty() {
+        None
+    } else {
+        Some(runs.join(" "))
+    }
+}
+
+/// Every tag with text, along with its text.
+fn text_nodes<'b, 'a>(tree: &'b StyleTree<'a>) -> Vec<(&'b StyleNode<'a>, String)> {
+    tree.iter()
+        .filter_map(|node| text_content(tree, node).map(|content| (node, content)))
+        .collect()
+}
+
+/// Gives every tag with text the edit variables `intrinsic_width`, the width
+/// of its text on one line, and `intrinsic_height`, the height of its text
+/// wrapped at its width. The tag is then kept at least as tall as its text at
+/// `strength`, and weakly at least as wide, so a width set by the stylesheet
+/// wraps the text instead.
+///
+/// Heights start out measured without wrapping. Call `reflow_text` after
+/// solving to measure them at the solved widths.
+pub fn add_text_constraints(
+    tree: &StyleTree,
+    variable_pool: &mut VariablePool,
+    solver: &mut Solver,
+    measure: &dyn MeasureText,
+    strength: f64,
+) {
+    for (node, content) in text_nodes(tree) {
+        let (width, height) = measure.measure_text(&content, node.font_size, None);
+        let intrinsic_width = retrieve_variable(variable_pool, node.id, "intrinsic_width");
+        let intrinsic_height = retrieve_variable(variable_pool, node.id, "intrinsic_height");
+        let node_width = retrieve_variable(variable_pool, node.id, "width");
+        let node_height = retrieve_variable(variable_pool, node.id, "height");
+        solver
+            .add_constraints(&[
+                node_width | WeightedRelation::GE(WEAK) | intrinsic_width,
+                node_height | WeightedRelation::GE(strength) | intrinsic_height,
+            ])
+            .unwrap();
+        for (variable, value) in &[(intrinsic_width, width), (intrinsic_height, height)] {
+            solver.add_edit_variable(*variable, EDIT_STRENGTH).unwrap();
+            solver.suggest_value(*variable, *value).unwrap();
+        }
+    }
+}
+
+/// Re-measures the height of every tag's text at its solved width until the
+/// heights settle. Returns how many passes changed a height.
+pub fn reflow_text(
+    tree: &StyleTree,
+    variable_pool: &mut VariablePool,
+    solver: &mut Solver,
+    measure: &dyn MeasureText,
+) -> Result<usize, StyleError> {
+    let nodes = text_nodes(tree);
+    settle(|| {
+        let mut settled = true;
+        for (node, content) in &nodes {
+            let width = solver.get_value(retrieve_variable(variable_pool, node.id, "width"));
+            let intrinsic_height = retrieve_variable(variable_pool, node.id, "intrinsic_height");
+            let max_width = if width > 0.0 { Some(width) } else { None };
+            let (_, height) = measure.measure_text(content, node.font_size, max_width);
+            if (solver.get_value(intrinsic_height) - height).abs() > REFLOW_TOLERANCE {
+                suggest_value(solver, intrinsic_height, height)?;
+                settled = false;
+            }
+        }
+        Ok(settled)
+    })
+}
+
+#[cfg(test)]
+mod text_tests {
+    use super::*;
+    use crate::dom::fixture;
+    use crate::dom::style_tree::{solve_constraints, DefaultConstraints, NodeId};
+
+    /// Every character is 10 by 20 pixels, and lines wrap at any character.
+    struct Monospace;
+
+    impl MeasureText for Monospace {
+        fn measure_text(&self, content: &str, _px: f64, max_width: Option<f64>) -> (f64, f64) {
+            let width = content.len() as f64 * 10.0;
+            let per_line = max_width.map_or(content.len(), |max_width| {
+                ((max_width / 10.0).floor() as usize).max(1)
+            });
+            let lines = content.len().div_ceil(per_line);
+            (width.min(per_line as f64 * 10.0), lines as f64 * 20.0)
+        }
+    }
+
+    /// Lays out `markup` in an 800x600 window and returns the width and
+    /// height of the tag with id 1.
+    fn size(sheet: &str, markup: &str) -> (f64, f64) {
+        let defaults = DefaultConstraints::none();
+        fixture::with_style(sheet, markup, &defaults, |tree, variable_pool, solver| {
+            add_text_constraints(tree, variable_pool, solver, &Monospace, TEXT_STRENGTH);
+            solve_constraints(tree, variable_pool, solver)?;
+            reflow_text(tree, variable_pool, solver, &Monospace)?;
+            let width = retrieve_variable(variable_pool, NodeId(1), "width");
+            let height = retrieve_variable(variable_pool, NodeId(1), "height");
+            Ok((solver.get_value(width), solver.get_value(height)))
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn intrinsic_size() {
+        assert_eq!(size("", "<body><a>hello</a></body>"), (50.0, 20.0));
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(
+            size("a { width = 20 }", "<body><a>hello</a></body>"),
+            (20.0, 60.0)
+        );
+    }
+
+    #[test]
+    fn reflow() {
+        // The width depends on the parent, which is only known after solving
+        assert_eq!(
+            size(
+                "b { width = 30 } a { width = $parent[width] }",
+                "<b><a>hello</a></b>"
+            ),
+            (30.0, 40.0)
+        );
+    }
+
+    #[test]
+    fn required_height() {
+        assert_eq!(
+            size("a { height = 10 }", "<body><a>hello</a></body>"),
+            (50.0, 10.0)
+        );
+    }
+}
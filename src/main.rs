@@ -1,101 +1,111 @@
-use ass::dom::{
-    construct_style_tree, generate_render_tree, generate_variable_pool, solve_constraints,
-};
-use ass::parser::parser;
-use cassowary::strength::{REQUIRED, STRONG, WEAK};
-use cassowary::WeightedRelation::*;
-use cassowary::{Solver, Variable};
-use std::collections::{HashMap, HashSet};
+use ass::dom::VariablePool;
+use cassowary::Solver;
 use minifb::{Key, ScaleMode, Window, WindowOptions};
-use ass::display::{Scene, rgb_to_u32};
-use std::fs::read_to_string;
+use ass::display::{render_document, Document, Scene};
+use std::fs::{read_to_string, File};
 use std::env;
 use std::process::exit;
 
-fn get_input() -> String {
-    let mut buffer = String::new();
-    std::io::stdin()
-        .read_line(&mut buffer)
-        .expect("Failed to read input");
-    buffer
-}
-
-fn print_changes(variable_pool: &HashMap<usize, HashMap<&String, Variable>>, solver: &Solver) {
+fn print_changes(variable_pool: &VariablePool, solver: &Solver) {
     println!("Changes:");
     for (id, attr_to_var) in variable_pool {
         for (attr_name, var) in attr_to_var {
-            println!("{}[{}] = {}", id, attr_name, solver.get_value(*var))
+            println!("{}[{}] = {}", id.0, attr_name, solver.get_value(*var))
+        }
+    }
+}
+
+const USAGE: &str = "Usage: ass [filename]
+       ass render [filename] -o [output.png] [--size WIDTHxHEIGHT]";
+
+fn parse_size(size: &str) -> Option<(usize, usize)> {
+    let mut dimensions = size.split('x').map(|n| n.parse().ok());
+    match (dimensions.next(), dimensions.next(), dimensions.next()) {
+        (Some(Some(width)), Some(Some(height)), None) if width > 0 && height > 0 => {
+            Some((width, height))
+        }
+        _ => None,
+    }
+}
+
+/// Renders a document to a PNG file without opening a window.
+fn render(args: &[String]) {
+    let mut filename = None;
+    let mut output = None;
+    let mut size = (800, 600);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = args.next(),
+            "--size" => {
+                size = match args.next().and_then(|size| parse_size(size)) {
+                    Some(size) => size,
+                    None => {
+                        eprintln!("error: --size expects WIDTHxHEIGHT, as in 800x600");
+                        exit(1);
+                    }
+                }
+            }
+            _ if filename.is_none() => filename = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                exit(1);
+            }
+        }
+    }
+    let (filename, output) = match (filename, output) {
+        (Some(filename), Some(output)) => (filename, output),
+        _ => {
+            eprintln!("{}", USAGE);
+            exit(1);
+        }
+    };
+    let contents = match read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(_) => {
+            eprintln!("error: {}: invalid file", filename);
+            exit(1);
         }
+    };
+    let scene = match render_document(&contents, size.0, size.1) {
+        Ok(scene) => scene,
+        Err(e) => {
+            eprintln!("error: {}: {}", filename, e);
+            exit(1);
+        }
+    };
+    let result = File::create(output)
+        .map_err(|e| e.to_string())
+        .and_then(|file| scene.write_png(file).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        eprintln!("error: {}: {}", output, e);
+        exit(1);
     }
 }
 
 fn main() {
-    let code = if let Some(filename) = env::args().collect::<Vec<String>>().get(1) {
+    let args = env::args().collect::<Vec<String>>();
+    if args.get(1).map(String::as_str) == Some("render") {
+        render(&args[2..]);
+        return;
+    }
+    let document = if let Some(filename) = args.get(1) {
         if let Ok(contents) = read_to_string(filename) {
-            parser(&contents)
+            match Document::parse(&contents) {
+                Ok(document) => document,
+                Err(e) => {
+                    eprintln!("error: {}: {}", filename, e);
+                    exit(1);
+                }
+            }
         } else {
             eprintln!("error: {}: invalid file", filename);
             exit(1);
         }
     } else {
-        eprintln!("Usage: ass [filename]");
+        eprintln!("{}", USAGE);
         exit(1);
-    }.unwrap();
-
-    // println!("{:#?}", parsed_code);
-    let mut solver = Solver::new();
-    let window_width = Variable::new();
-    let window_height = Variable::new();
-
-    let constraint_names: HashSet<String> = [
-        "left".to_string(),
-        "right".to_string(),
-        "top".to_string(),
-        "bottom".to_string(),
-        "width".to_string(),
-        "height".to_string(),
-        "x".to_string(),
-        "y".to_string(),
-    ]
-    .iter()
-    .cloned()
-    .collect();
-
-    let property_names: HashSet<String> =
-        ["background-color".to_string()].iter().cloned().collect();
-
-    solver.add_constraints(&[
-        window_width | EQ(REQUIRED) | 800.0,
-        window_height | EQ(REQUIRED) | 600.0,
-    ]);
-
-    let default_attributes = HashMap::new();
-
-    let style_tree = construct_style_tree(
-        &code.0,
-        &code.1,
-        &constraint_names,
-        &property_names,
-        0,
-        &default_attributes,
-    );
-
-    let mut variable_pool = HashMap::new();
-
-    generate_variable_pool(
-        &style_tree,
-        &code.1,
-        &constraint_names,
-        &mut variable_pool,
-    );
-
-    solve_constraints(&style_tree, &mut variable_pool, &mut solver);
-    println!("{:#?}", style_tree);
-    println!("{:#?}", variable_pool);
-    print_changes(&variable_pool, &solver);
-    let render_tree = generate_render_tree(&style_tree, &solver, &mut variable_pool);
-    println!("{:#?}", render_tree);
-
+    };
 
     let mut window = Window::new(
         "ASS",
@@ -108,17 +118,51 @@ fn main() {
         },
     ).expect("Unable to create window");
 
-    let mut scene = Scene::new(500, 500);
+    let mut size = window.get_size();
+    let mut scene = Scene::new(size.0, size.1);
+
+    let mut layout = match document.lay_out(&scene, size.0, size.1) {
+        Ok(layout) => layout,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            exit(1);
+        }
+    };
+    println!("{:#?}", layout.style_tree);
+    println!("{:#?}", layout.variable_pool);
+    print_changes(&layout.variable_pool, &layout.solver);
+    let mut render_tree = match layout.render_tree() {
+        Ok(render_tree) => render_tree,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            exit(1);
+        }
+    };
+    println!("{:#?}", render_tree);
 
     // Limit to max ~60 fps update rate
     window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
+        if window.get_size() != size {
+            size = window.get_size();
+            let resized = layout.resize(&scene, size.0, size.1).and_then(|changed| {
+                if changed {
+                    layout.render_tree().map(Some)
+                } else {
+                    Ok(None)
+                }
+            });
+            match resized {
+                Ok(Some(tree)) => render_tree = tree,
+                Ok(None) => {}
+                // Keep drawing the last render tree that could be generated
+                Err(e) => eprintln!("error: {}", e),
+            }
+        }
         scene.clear();
-        scene.maybe_resize(window.get_size());
-        scene.add_rect(20.0, 20.0, 100.0, 100.0, rgb_to_u32(100, 200, 100));
+        scene.maybe_resize(size);
         scene.process_render_tree(&render_tree);
-        scene.add_text("Hello can we write a very long thing that might possibly induce a line break, or nah", 30.0, 50.0, 50.0, 300.0, 100.0);
         scene.update_window(&mut window);
     }
 }
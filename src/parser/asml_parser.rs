@@ -9,10 +9,32 @@ pub enum Element {
     Text(String),
     Tag {
         traits: Vec<Trait>,
+        /// Set with `#name` among the traits, unique in a document
+        id: Option<String>,
         children: Vec<Element>,
     },
 }
 
+/// Something written in an opening tag.
+enum Marker {
+    Trait(Trait),
+    Id(String),
+}
+
+/// Separates the traits of a tag from its id, of which it can have one.
+fn split_markers(markers: Vec<Marker>) -> Result<(Vec<Trait>, Option<String>), &'static str> {
+    let mut traits = Vec::new();
+    let mut id = None;
+    for marker in markers {
+        match marker {
+            Marker::Trait(trait_) => traits.push(trait_),
+            Marker::Id(_) if id.is_some() => return Err("at most one #id per tag"),
+            Marker::Id(name) => id = Some(name),
+        }
+    }
+    Ok((traits, id))
+}
+
 peg::parser! {
     pub grammar asml_parser() for str {
         rule comment() = "<#" (!"#>"[c])* "#>"
@@ -20,26 +42,43 @@ peg::parser! {
         rule whitespace() = quiet!{([c if c.is_whitespace()]+ / comment())+ }
 
         rule word() -> String
-            = s:$(['a'..='z' | '_' | '0'..='9']+) { s.to_string() }
-
-        rule word_extra() -> String
-            = s:$([c if !matches!(c, '<' | '>' | '(' | ')') && !c.is_whitespace() ]+) { s.to_string() }
+            = quiet!{ s:$(['a'..='z' | '_' | '0'..='9']+) { s.to_string() } } / expected!("identifier")
 
         rule paragraph() -> String
             = s:$([c if c != '<']+) { s.trim().to_string() }
 
+        // Parentheses in an argument have to be balanced, and can hold
+        // whitespace, so `rgb(0, 0, 0)` is a single argument
+        rule group() = "(" ([c if !matches!(c, '(' | ')')] / group())* ")"
+
+        rule arg() -> String
+            = s:$(([c if !matches!(c, '<' | '>' | '(' | ')') && !c.is_whitespace()] / group())+) { s.to_string() }
+
         rule args() -> Vec<String>
-            = "(" whitespace()* args:word_extra() ** whitespace() whitespace()* ")" { args }
+            = "(" whitespace()* args:arg() ** whitespace() whitespace()* ")" { args }
 
         rule traits() -> Trait
             = name:word() args:args()? { Trait { name, args: args.unwrap_or_default() } }
 
+        rule marker() -> Marker
+            = "#" name:word() { Marker::Id(name) }
+            / t:traits() { Marker::Trait(t) }
+
+        // The first marker has to be a trait: `<#` starts a comment, and the
+        // closing tag repeats the first trait
+        rule opening() -> (Vec<Trait>, Option<String>)
+            = first:traits() rest:(whitespace() m:marker() { m })* {?
+                split_markers(std::iter::once(Marker::Trait(first)).chain(rest).collect())
+            }
+            / { (Vec::new(), None) }
+
         pub rule tag() -> Element
             = s:paragraph() { Element::Text(s) }
-            / whitespace()* "<" whitespace()* traits:traits() ** whitespace() whitespace()* "/>" whitespace()* { Element::Tag {traits, children: Vec::new() }}
-            / whitespace()* "<" whitespace()* traits:traits() ** whitespace() whitespace()* ">"  whitespace()* children:tag()* "</" close:word() whitespace()* ">" whitespace()* {?
-                if traits.get(0).map_or(false, |x| x.name == close) {
-                    Ok(Element::Tag { traits, children })
+            / whitespace()* "<" whitespace()* opening:opening() whitespace()* "/>" whitespace()* { Element::Tag { traits: opening.0, id: opening.1, children: Vec::new() }}
+            / whitespace()* "<" whitespace()* opening:opening() whitespace()* ">"  whitespace()* children:tag()* "</" close:word() whitespace()* ">" whitespace()* {?
+                let (traits, id) = opening;
+                if traits.first().is_some_and(|x| x.name == close) {
+                    Ok(Element::Tag { traits, id, children })
                 } else {
                     Err("didn't find closing trait")
                 }
@@ -53,13 +92,14 @@ mod asml_parser_tests {
     use super::*;
 
     fn h1_element() -> Result<Element, peg::error::ParseError<peg::str::LineCol>> {
-        return Ok(Element::Tag {
+        Ok(Element::Tag {
             traits: vec![Trait {
                 name: "h1".to_string(),
                 args: Vec::new(),
             }],
+            id: None,
             children: Vec::new(),
-        });
+        })
     }
 
     #[test]
@@ -76,11 +116,13 @@ mod asml_parser_tests {
                     name: String::from("tag1"),
                     args: vec![],
                 },],
+                id: None,
                 children: vec![Element::Tag {
                     traits: vec![Trait {
                         name: String::from("tag2"),
                         args: vec![],
                     },],
+                    id: None,
                     children: vec![],
                 },],
             },),
@@ -96,6 +138,7 @@ mod asml_parser_tests {
                     name: "h1".to_string(),
                     args: Vec::new(),
                 }],
+                id: None,
                 children: vec![Element::Text("god".to_string())],
             }),
         );
@@ -110,6 +153,7 @@ mod asml_parser_tests {
                     name: "h1".to_string(),
                     args: Vec::new(),
                 }],
+                id: None,
                 children: vec![Element::Text("god".to_string()), h1_element().unwrap()],
             }),
         );
@@ -153,6 +197,7 @@ mod asml_parser_tests {
                     name: "h1".to_string(),
                     args: vec!["god".to_string()],
                 }],
+                id: None,
                 children: Vec::new(),
             })
         )
@@ -167,11 +212,50 @@ mod asml_parser_tests {
                     name: "h1".to_string(),
                     args: vec!["god".to_string(), "o".to_string()],
                 }],
+                id: None,
+                children: Vec::new(),
+            })
+        )
+    }
+
+    #[test]
+    fn test_nested_args() {
+        assert_eq!(
+            asml_parser::tag("<tag(rgb(0, (1), 2) red)/>"),
+            Ok(Element::Tag {
+                traits: vec![Trait {
+                    name: "tag".to_string(),
+                    args: vec!["rgb(0, (1), 2)".to_string(), "red".to_string()],
+                }],
+                id: None,
                 children: Vec::new(),
             })
         )
     }
 
+    #[test]
+    fn test_id() {
+        assert_eq!(
+            asml_parser::tag("<post #first <# #> wide(2)></post>"),
+            Ok(Element::Tag {
+                traits: vec![
+                    Trait {
+                        name: "post".to_string(),
+                        args: Vec::new(),
+                    },
+                    Trait {
+                        name: "wide".to_string(),
+                        args: vec!["2".to_string()],
+                    },
+                ],
+                id: Some("first".to_string()),
+                children: Vec::new(),
+            })
+        );
+        assert!(asml_parser::tag("<post #first #second/>").is_err());
+        assert!(asml_parser::tag("<post #/>").is_err());
+    }
+
     #[test]
     fn test_multi_args_with_comments() {
         assert_eq!(
@@ -181,6 +265,7 @@ mod asml_parser_tests {
                     name: "h1".to_string(),
                     args: vec!["god".to_string(), "o".to_string()],
                 }],
+                id: None,
                 children: Vec::new(),
             })
         )
@@ -194,6 +279,7 @@ mod asml_parser_tests {
                     name: "h1".to_string(),
                     args: vec!["god".to_string(), "o".to_string()],
                 }],
+                id: None,
                 children: vec![
                     Element::Text("b3%!".to_string()),
                     Element::Tag {
@@ -201,6 +287,7 @@ mod asml_parser_tests {
                             name: "h1".to_string(),
                             args: vec!["god".to_string()],
                         }],
+                        id: None,
                         children: vec![Element::Text("()".to_string())],
                     },
                     Element::Text("^423$%33(4)232".to_string())
@@ -219,6 +306,7 @@ mod asml_parser_tests {
                     name: "h1".to_string(),
                     args: vec!["god".to_string()],
                 }],
+                id: None,
                 children: Vec::new(),
             })
         )
@@ -234,6 +322,7 @@ mod asml_parser_tests {
                     name: "h1".to_string(),
                     args: vec!["god".to_string()],
                 }],
+                id: None,
                 children: Vec::new(),
             })
         )
@@ -249,6 +338,7 @@ mod asml_parser_tests {
                     name: "h1".to_string(),
                     args: vec!["god".to_string()],
                 }],
+                id: None,
                 children: Vec::new(),
             })
         )
@@ -1,38 +1,176 @@
-use std::collections::HashMap;
-use std::convert::TryInto;
+use super::color::Color;
+use std::fmt;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Entity {
     Parent,
     Window,
+    /// The previous sibling, or the closest previous one with the trait
+    Prev(Option<String>),
+    /// The next sibling, or the closest next one with the trait
+    Next(Option<String>),
+    /// An id, or else a trait
     Other(String),
 }
 
-#[derive(Debug, PartialEq)]
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Entity::Parent => write!(f, "$parent"),
+            Entity::Window => write!(f, "$window"),
+            Entity::Prev(None) => write!(f, "$prev"),
+            Entity::Prev(Some(name)) => write!(f, "$prev({})", name),
+            Entity::Next(None) => write!(f, "$next"),
+            Entity::Next(Some(name)) => write!(f, "$next({})", name),
+            Entity::Other(name) => write!(f, "${}", name),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Relation {
     GE,
     EQ,
     LE,
 }
 
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Relation::GE => write!(f, ">="),
+            Relation::EQ => write!(f, "="),
+            Relation::LE => write!(f, "<="),
+        }
+    }
+}
+
+/// A trait on the right hand side of an alias, e.g. `border_color(color - 10%)`.
+/// Its arguments may refer to the alias' parameters.
 #[derive(Debug, PartialEq)]
-pub struct Alias {
-    from: String,
-    to: Vec<String>,
+pub struct AliasTrait {
+    pub name: String,
+    pub args: Vec<Arith>,
 }
 
+/// `from(params) = to...`. An alias must be given exactly as many arguments
+/// as it has parameters, so one without parameters takes no arguments.
 #[derive(Debug, PartialEq)]
+pub struct Alias {
+    pub from: String,
+    pub params: Vec<String>,
+    pub to: Vec<AliasTrait>,
+}
+
+/// Strength of a constraint, from `!strong` and the like. A weight is used
+/// as a raw solver strength, where weak is 1, medium is 1000, strong is
+/// 1000000 and required is anything above 1001001000.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Strength {
+    Required,
+    Strong,
+    Medium,
+    Weak,
+    Weight(f64),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Unit {
+    Px,
+    /// Of the parent's width or height, or of the parent's font size for
+    /// `font_size`
+    Percent,
+    /// Of the font size of the root element
+    Rem,
+    /// Of the element's own font size, or of the parent's for `font_size`
+    Em,
+    /// Of the window's width
+    Vw,
+    /// Of the window's height
+    Vh,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Length {
+    pub value: f64,
+    pub unit: Unit,
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let unit = match self.unit {
+            Unit::Px => "px",
+            Unit::Percent => "%",
+            Unit::Rem => "rem",
+            Unit::Em => "em",
+            Unit::Vw => "vw",
+            Unit::Vh => "vh",
+        };
+        write!(f, "{}{}", self.value, unit)
+    }
+}
+
+/// One constraint on an attribute, such as `<= $parent[width] !strong`
+pub type Term = (Relation, Arith, Option<Strength>);
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Arith {
     Ref(Entity, String),
-    Num(u32),
+    Num(f64),
+    Length(Length),
+    Color(Color),
+    /// A bare word, such as a parameter name or a keyword
+    Ident(String),
+    /// An argument that isn't a valid expression, kept verbatim
+    Str(String),
     Add(Box<Arith>, Box<Arith>),
     Sub(Box<Arith>, Box<Arith>),
+    Mul(Box<Arith>, Box<Arith>),
+    Div(Box<Arith>, Box<Arith>),
+    Neg(Box<Arith>),
+    /// A function applied to its arguments, such as `rgb(255, 0, 0)`
+    Call(String, Vec<Arith>),
 }
 
+impl fmt::Display for Arith {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Operands that are themselves operations are always parenthesized
+        let operand = |a: &Arith| match a {
+            Arith::Add(..) | Arith::Sub(..) | Arith::Mul(..) | Arith::Div(..) => format!("({})", a),
+            _ => a.to_string(),
+        };
+        match self {
+            Arith::Ref(entity, attr_name) => write!(f, "{}[{}]", entity, attr_name),
+            Arith::Num(n) => write!(f, "{}", n),
+            Arith::Length(length) => write!(f, "{}", length),
+            Arith::Color(color) => write!(f, "{}", color),
+            Arith::Ident(s) | Arith::Str(s) => write!(f, "{}", s),
+            Arith::Add(a, b) => write!(f, "{} + {}", operand(a), operand(b)),
+            Arith::Sub(a, b) => write!(f, "{} - {}", operand(a), operand(b)),
+            Arith::Mul(a, b) => write!(f, "{} * {}", operand(a), operand(b)),
+            Arith::Div(a, b) => write!(f, "{} / {}", operand(a), operand(b)),
+            Arith::Neg(a) => write!(f, "-{}", operand(a)),
+            Arith::Call(name, args) => {
+                let args = args.iter().map(Arith::to_string).collect::<Vec<_>>();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+        }
+    }
+}
+
+/// `name(params) { attrs }`. Parameters follow the same arity rules as
+/// `Alias` parameters. Attributes keep their source order, and an attribute
+/// may appear more than once.
 #[derive(Debug, PartialEq)]
 pub struct Style {
     pub name: String,
-    pub attrs: HashMap<String, Vec<(Relation, Arith)>>,
+    pub params: Vec<String>,
+    pub attrs: Vec<(String, Vec<Term>)>,
+}
+
+#[derive(Debug, PartialEq, Default)]
+pub struct Stylesheet {
+    pub styles: Vec<Style>,
+    pub aliases: Vec<Alias>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -50,49 +188,126 @@ peg::parser! {
         rule whitespace() = quiet!{ [c if c.is_whitespace()]+ }
 
         rule word() -> String
-            = s:$(['a'..='z' | '_' | '0'..='9']+) { s.to_string() }
+            = quiet!{ s:$(['a'..='z' | '_' | '0'..='9']+) { s.to_string() } } / expected!("identifier")
+
+        rule number() -> f64
+            = quiet!{ decimal() } / expected!("number")
+
+        rule word_extra() -> String
+            = s:$([c if !matches!(c, '<' | '>' | '(' | ')') && !c.is_whitespace() ]+) { s.to_string() }
+
+        rule params() -> Vec<String>
+            = "(" whitespace()* params:word() ** whitespace() whitespace()* ")" { params }
+
+        rule arg() -> Arith
+            = a:arith() &(whitespace() / ")") { a }
+            / s:word_extra() { Str(s) }
+
+        rule args() -> Vec<Arith>
+            = "(" whitespace()* args:arg() ** whitespace() whitespace()* ")" { args }
+
+        rule alias_trait() -> AliasTrait
+            = name:word() args:args()? { AliasTrait { name, args: args.unwrap_or_default() } }
 
-        rule number() -> u32
-            = n:$(['0'..='9']+) { n.parse().unwrap() }
+        rule decimal() -> f64
+            = n:$(['0'..='9']+ ("." ['0'..='9']+)? / "." ['0'..='9']+) { n.parse().unwrap() }
 
-        rule hex() -> u32
-            = "#" n:$(['0'..='9' | 'A'..='F' | 'a'..='f']*<6>) { u32::from_str_radix(n, 16).unwrap() }
+        rule unit() -> Unit
+            = "px" { Unit::Px }
+            / "%" { Unit::Percent }
+            / "rem" { Unit::Rem }
+            / "em" { Unit::Em }
+            / "vw" { Unit::Vw }
+            / "vh" { Unit::Vh }
+
+        rule length() -> super::Length
+            = quiet!{ value:decimal() unit:unit() { super::Length { value, unit } } } / expected!("length")
+
+        rule hex() -> super::Color
+            = "#" n:$(['0'..='9' | 'A'..='F' | 'a'..='f']*<3,8>) {?
+                super::Color::from_hex(n).ok_or("3, 4, 6 or 8 hex digits")
+            }
+
+        rule call() -> Arith
+            = name:word() "(" whitespace()* args:arith() ** (whitespace()* "," whitespace()*) whitespace()* ")" {
+                Call(name, args)
+            }
 
         rule relation() -> Relation
             = "=" { EQ } / "<=" { LE } / ">=" { GE }
 
+        rule sibling_trait() -> String
+            = "(" whitespace()* w:word() whitespace()* ")" { w }
+
         rule entity() -> Entity
             = "$parent" { Entity::Parent }
             / "$window" { Entity::Window }
+            / "$prev" !word() t:sibling_trait()? { Entity::Prev(t) }
+            / "$next" !word() t:sibling_trait()? { Entity::Next(t) }
+            / "$" w:word() { Entity::Other(w) }
             / w:word() { Entity::Other(w) }
 
         rule attribute() -> Arith
             = e:entity() "[" w:word() "]" { Ref(e, w) }
 
         rule attr_or_val() -> Arith
-            = attribute() / n:number() { Num(n) } / n:hex() { Num(n) }
-
-        pub rule alias() -> Alias 
-            = from:word() whitespace()* "=" whitespace()* to:word() ** " " { Alias { from, to } }
+            = attribute() / l:length() { Arith::Length(l) } / n:number() { Num(n) } / c:hex() { Color(c) } / call() / w:word() { Ident(w) }
 
-        rule arith() -> Arith
-            = p1:attribute() whitespace()* "-" whitespace()* p2:attr_or_val() { Sub(Box::new(p1), Box::new(p2)) }
-            / p1:attribute() whitespace()* "+" whitespace()* p2:attr_or_val() { Add(Box::new(p1), Box::new(p2)) }
-            / p:attribute() whitespace()* "-" whitespace()* a:arith() { Sub(Box::new(p), Box::new(a)) }
-            / p:attribute() whitespace()* "+" whitespace()* a:arith() { Sub(Box::new(p), Box::new(a)) }
-            / attr_or_val()
+        rule inline_whitespace() = quiet!{ [' ' | '\t']+ }
 
-        rule constraint() -> (Relation, Arith)
-            = whitespace()* r:relation() whitespace()* a:arith() { (r, a) }
+        pub rule alias() -> Alias
+            = from:word() params:params()? whitespace()* "=" inline_whitespace()? to:alias_trait() ++ inline_whitespace() {
+                Alias { from, params: params.unwrap_or_default(), to }
+            }
 
-        rule spec() -> (String, Vec<(Relation, Arith)>)
+        // Operators are left associative, `*` and `/` bind tighter than `+`
+        // and `-`, and unary minus binds tightest
+        rule arith() -> Arith = precedence!{
+            a:(@) whitespace()* "+" whitespace()* b:@ { Add(Box::new(a), Box::new(b)) }
+            a:(@) whitespace()* "-" whitespace()* b:@ { Sub(Box::new(a), Box::new(b)) }
+            --
+            a:(@) whitespace()* "*" whitespace()* b:@ { Mul(Box::new(a), Box::new(b)) }
+            a:(@) whitespace()* "/" whitespace()* b:@ { Div(Box::new(a), Box::new(b)) }
+            --
+            "-" whitespace()* a:@ { Neg(Box::new(a)) }
+            --
+            "(" whitespace()* a:arith() whitespace()* ")" { a }
+            a:attr_or_val() { a }
+        }
+
+        // A single value, used to parse trait arguments from the markup
+        pub rule value() -> Arith
+            = whitespace()* a:arith() whitespace()* { a }
+
+        rule weight() -> f64
+            = quiet!{ decimal() } / expected!("weight")
+
+        rule strength() -> Strength
+            = "!" s:(
+                "required" { Strength::Required }
+                / "strong" { Strength::Strong }
+                / "medium" { Strength::Medium }
+                / "weak" { Strength::Weak }
+                / w:weight() { Strength::Weight(w) }
+            ) { s }
+
+        rule constraint() -> Term
+            = whitespace()* r:relation() whitespace()* a:arith() s:(whitespace()* s:strength() { s })? { (r, a, s) }
+
+        rule spec() -> (String, Vec<Term>)
             = attr:word() c:constraint() ** ", else" { (attr, c) }
 
         rule style_or_alias() -> StyleOrAlias
-            = name:word() whitespace()* "{" whitespace()* attr:spec() ** whitespace() whitespace()* "}" { OrStyle(Style { name, attrs: attr.into_iter().collect::<HashMap<String, Vec<(Relation, Arith)>>>() }) }
+            = name:word() params:params()? whitespace()* "{" whitespace()* attr:spec() ** whitespace() whitespace()* "}" {
+                OrStyle(Style {
+                    name,
+                    params: params.unwrap_or_default(),
+                    attrs: attr,
+                })
+            }
             / a:alias() { OrAlias(a) }
 
-        pub rule stylesheet() -> Vec<Style>
+        pub rule stylesheet() -> Stylesheet
             = whitespace()* s:style_or_alias() ** (whitespace()*) {
                 let mut styles = Vec::new();
                 let mut aliases = Vec::new();
@@ -102,7 +317,7 @@ peg::parser! {
                         OrStyle(s) => styles.push(s),
                     }
                 }
-                styles
+                Stylesheet { styles, aliases }
             }
     }
 }
@@ -111,46 +326,55 @@ peg::parser! {
 mod ass_parser_tests {
     use super::*;
     
-    macro_rules! hashmap {
-        ($( $key: expr => $val: expr ),*) => {{
-             let mut map = ::std::collections::HashMap::new();
-             $( map.insert($key, $val); )*
-             map
-        }}
+    macro_rules! attrs {
+        ($( $key: expr => $val: expr ),*) => {
+             vec![$( ($key, $val) ),*]
+        }
     }
     
+    fn styles(styles: Vec<Style>) -> Stylesheet {
+        Stylesheet {
+            styles,
+            aliases: Vec::new(),
+        }
+    }
+
     #[test]
     fn basic_style_attr() {
-        let expected = Ok(vec![Style {
+        let expected = Ok(styles(vec![Style {
             name: "div".to_string(),
-            attrs: hashmap!["width".to_string() => vec![(Relation::EQ, Arith::Num(32))]],
-        }]);
+            params: Vec::new(),
+            attrs: attrs!["width".to_string() => vec![(Relation::EQ, Arith::Num(32.0), None)]],
+        }]));
         let output = ass_parser::stylesheet("div{width= 32}");
         assert_eq!(output, expected);
     }
 
     #[test]
     fn empty_style() {
-        let expected = Ok(vec![Style {
+        let expected = Ok(styles(vec![Style {
             name: "div".to_string(),
-            attrs: HashMap::new(),
-        }]);
+            params: Vec::new(),
+            attrs: Vec::new(),
+        }]));
         let output = ass_parser::stylesheet("div{}");
         assert_eq!(output, expected);
     }
 
     #[test]
     fn two_empty_style() {
-        let expected = Ok(vec![
+        let expected = Ok(styles(vec![
             Style {
                 name: "div".to_string(),
-                attrs: HashMap::new(),
+                params: Vec::new(),
+                attrs: Vec::new(),
             },
             Style {
                 name: "div2".to_string(),
-                attrs: HashMap::new(),
+                params: Vec::new(),
+                attrs: Vec::new(),
             },
-        ]);
+        ]));
 
         let output = ass_parser::stylesheet("div{}div2{}");
         assert_eq!(output, expected);
@@ -158,27 +382,29 @@ mod ass_parser_tests {
 
     #[test]
     fn entity_attr() {
-        let expected = Ok(vec![Style {
+        let expected = Ok(styles(vec![Style {
             name: "div".to_string(),
-            attrs: hashmap!["width".to_string() => vec![(Relation::EQ, Arith::Ref(Entity::Parent, "width".to_string()))]],
-        }]);
+            params: Vec::new(),
+            attrs: attrs!["width".to_string() => vec![(Relation::EQ, Arith::Ref(Entity::Parent, "width".to_string()), None)]],
+        }]));
         let output = ass_parser::stylesheet("div{width=$parent[width]}");
         assert_eq!(output, expected);
     }
 
     #[test]
     fn multiple_attr() {
-        let expected = Ok(vec![Style {
+        let expected = Ok(styles(vec![Style {
             name: "div".to_string(),
-            attrs: hashmap!["width".to_string() => vec![
-                (Relation::EQ, Arith::Ref(Entity::Parent, "width".to_string())
+            params: Vec::new(),
+            attrs: attrs!["width".to_string() => vec![
+                (Relation::EQ, Arith::Ref(Entity::Parent, "width".to_string()), None
             )
 
             ], "height".to_string() => vec![
-                (Relation::GE, Arith::Ref(Entity::Other("hello".to_string()), "world".to_string()))
+                (Relation::GE, Arith::Ref(Entity::Other("hello".to_string()), "world".to_string()), None)
             ]
             ],
-        }]);
+        }]));
 
         let output = ass_parser::stylesheet(
             "div{
@@ -191,25 +417,27 @@ mod ass_parser_tests {
 
     #[test]
     fn multiple_style_and_attr() {
-        let expected = Ok(vec![
+        let expected = Ok(styles(vec![
             Style {
                 name: "div".to_string(),
-                attrs: hashmap!["width".to_string() => vec![
-                    (Relation::EQ, Arith::Ref(Entity::Parent, "width".to_string())
+                params: Vec::new(),
+                attrs: attrs!["width".to_string() => vec![
+                    (Relation::EQ, Arith::Ref(Entity::Parent, "width".to_string()), None
                 )
 
                 ], "height".to_string() => vec![
-                    (Relation::GE, Arith::Ref(Entity::Other("hello".to_string()), "world".to_string()))
+                    (Relation::GE, Arith::Ref(Entity::Other("hello".to_string()), "world".to_string()), None)
                 ]
                 ],
             },
             Style {
                 name: "god".to_string(),
-                attrs: hashmap!["strength".to_string() => vec![
-                    (Relation::LE, Arith::Num(30))
+                params: Vec::new(),
+                attrs: attrs!["strength".to_string() => vec![
+                    (Relation::LE, Arith::Num(30.0), None)
                 ]],
             },
-        ]);
+        ]));
 
         let output = ass_parser::stylesheet(
             "div {
@@ -221,4 +449,231 @@ mod ass_parser_tests {
         );
         assert_eq!(output, expected);
     }
+    #[test]
+    fn aliases() {
+        let expected = Ok(Stylesheet {
+            styles: vec![Style {
+                name: "par".to_string(),
+                params: Vec::new(),
+                attrs: Vec::new(),
+            }],
+            aliases: vec![
+                Alias {
+                    from: "par".to_string(),
+                    params: Vec::new(),
+                    to: vec![
+                        AliasTrait {
+                            name: "text_color".to_string(),
+                            args: vec![Arith::Ident("black".to_string())],
+                        },
+                        AliasTrait {
+                            name: "justify".to_string(),
+                            args: Vec::new(),
+                        },
+                    ],
+                },
+                Alias {
+                    from: "post".to_string(),
+                    params: Vec::new(),
+                    to: vec![AliasTrait {
+                        name: "par".to_string(),
+                        args: Vec::new(),
+                    }],
+                },
+            ],
+        });
+
+        let output = ass_parser::stylesheet(
+            "par = text_color(black) justify
+            post = par
+            par {}",
+        );
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn parameterized_alias() {
+        let expected = Ok(Alias {
+            from: "tag".to_string(),
+            params: vec!["color".to_string()],
+            to: vec![
+                AliasTrait {
+                    name: "border".to_string(),
+                    args: vec![
+                        Arith::Length(Length {
+                            value: 1.0,
+                            unit: Unit::Px
+                        }),
+                        Arith::Ident("solid".to_string()),
+                        Arith::Ident("color".to_string()),
+                    ],
+                },
+                AliasTrait {
+                    name: "text_color".to_string(),
+                    args: vec![Arith::Sub(
+                        Box::new(Arith::Ident("color".to_string())),
+                        Box::new(Arith::Num(20.0)),
+                    )],
+                },
+            ],
+        });
+        let output = ass_parser::alias("tag(color) = border(1px solid color) text_color(color - 20)");
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn parameterized_style() {
+        let expected = Ok(styles(vec![Style {
+            name: "size".to_string(),
+            params: vec!["w".to_string(), "h".to_string()],
+            attrs: attrs![
+                "width".to_string() => vec![(Relation::EQ, Arith::Ident("w".to_string()), None)],
+                "height".to_string() => vec![(Relation::EQ, Arith::Ident("h".to_string()), None)]
+            ],
+        }]));
+        let output = ass_parser::stylesheet("size(w h) { width = w height = h }");
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn strengths() {
+        let expected = Ok(styles(vec![Style {
+            name: "div".to_string(),
+            params: Vec::new(),
+            attrs: attrs![
+                "width".to_string() => vec![
+                    (Relation::LE, Arith::Num(90.0), Some(Strength::Strong)),
+                    (Relation::EQ, Arith::Num(90.0), Some(Strength::Weight(2.5)))
+                ],
+                "height".to_string() => vec![(Relation::EQ, Arith::Num(5.0), Some(Strength::Weak))],
+                "left".to_string() => vec![(Relation::EQ, Arith::Num(0.0), Some(Strength::Required))],
+                "top".to_string() => vec![(Relation::EQ, Arith::Num(0.0), Some(Strength::Medium))]
+            ],
+        }]));
+        let output = ass_parser::stylesheet(
+            "div {
+                width <= 90 !strong, else = 90 !2.5
+                height = 5 !weak
+                left = 0 !required
+                top = 0  !medium
+            }",
+        );
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn lengths() {
+        let length = |value, unit| Arith::Length(Length { value, unit });
+        let expected = Ok(styles(vec![Style {
+            name: "div".to_string(),
+            params: Vec::new(),
+            attrs: attrs![
+                "width".to_string() => vec![
+                    (Relation::LE, length(90.0, Unit::Rem), None),
+                    (Relation::EQ, length(75.0, Unit::Percent), None)
+                ],
+                "height".to_string() => vec![(Relation::EQ, length(0.5, Unit::Em), None)],
+                "left".to_string() => vec![(Relation::EQ, Arith::Add(
+                    Box::new(length(16.0, Unit::Px)),
+                    Box::new(length(2.5, Unit::Vw))
+                ), None)],
+                "top".to_string() => vec![(Relation::EQ, length(10.0, Unit::Vh), None)]
+            ],
+        }]));
+        let output = ass_parser::stylesheet(
+            "div {
+                width <= 90rem, else = 75%
+                height = .5em
+                left = 16px + 2.5vw
+                top = 10vh
+            }",
+        );
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let num = |n| Box::new(Arith::Num(n));
+        let parent = |attr_name: &str| Box::new(Arith::Ref(Entity::Parent, attr_name.to_string()));
+        let expected = Ok(styles(vec![Style {
+            name: "div".to_string(),
+            params: Vec::new(),
+            attrs: attrs![
+                "width".to_string() => vec![(Relation::EQ, Arith::Add(
+                    Box::new(Arith::Add(parent("left"), num(1.0))),
+                    num(2.0)
+                ), None)],
+                "height".to_string() => vec![(Relation::EQ, Arith::Sub(
+                    parent("height"),
+                    Box::new(Arith::Mul(num(0.5), parent("width")))
+                ), None)],
+                "left".to_string() => vec![(Relation::EQ, Arith::Div(
+                    Box::new(Arith::Sub(parent("width"), num(10.0))),
+                    num(3.0)
+                ), None)],
+                "top".to_string() => vec![(Relation::EQ, Arith::Neg(parent("top")), None)]
+            ],
+        }]));
+        let output = ass_parser::stylesheet(
+            "div {
+                width = $parent[left] + 1 + 2
+                height = $parent[height] - .5 * $parent[width]
+                left = ($parent[width] - 10) / 3
+                top = -$parent[top]
+            }",
+        );
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn arith_display() {
+        let output = ass_parser::value("($parent[width] - 10) / 3 + -2.5").unwrap();
+        assert_eq!(output.to_string(), "(($parent[width] - 10) / 3) + -2.5");
+    }
+
+    #[test]
+    fn colors() {
+        let value = |s| ass_parser::value(s).unwrap();
+        assert_eq!(value("#f80"), Arith::Color(Color::rgba(0xff, 0x88, 0, 255)));
+        assert_eq!(value("#12ab3480"), Arith::Color(Color::rgba(0x12, 0xab, 0x34, 0x80)));
+        assert!(ass_parser::value("#12345").is_err());
+        assert_eq!(
+            value("rgba(255, 50%, 0, .5)"),
+            Arith::Call(
+                "rgba".to_string(),
+                vec![
+                    Arith::Num(255.0),
+                    Arith::Length(Length { value: 50.0, unit: Unit::Percent }),
+                    Arith::Num(0.0),
+                    Arith::Num(0.5),
+                ]
+            )
+        );
+        assert_eq!(
+            value("darken(color, 10%) - 5%").to_string(),
+            "darken(color, 10%) - 5%"
+        );
+        assert_eq!(value("#FFF - 20%").to_string(), "#ffffff - 20%");
+    }
+
+    #[test]
+    fn siblings() {
+        let value = |s| ass_parser::value(s).unwrap();
+        assert_eq!(
+            value("$prev[bottom]"),
+            Arith::Ref(Entity::Prev(None), "bottom".to_string())
+        );
+        assert_eq!(
+            value("$next( post )[top]"),
+            Arith::Ref(Entity::Next(Some("post".to_string())), "top".to_string())
+        );
+        assert_eq!(
+            value("$previous[top]"),
+            Arith::Ref(Entity::Other("previous".to_string()), "top".to_string())
+        );
+        assert_eq!(
+            value("max(content[width], $prev(a)[width] + 1rem)").to_string(),
+            "max($content[width], $prev(a)[width] + 1rem)"
+        );
+    }
 }
@@ -0,0 +1,361 @@
+use std::fmt;
+
+/// A color with straight (not premultiplied) alpha.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Color {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+    pub alpha: u8,
+}
+
+/// The CSS named colors, sorted by name so they can be binary searched.
+const NAMED_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+impl Color {
+    pub const TRANSPARENT: Color = Color::rgba(0, 0, 0, 0);
+
+    pub const fn rgba(red: u8, green: u8, blue: u8, alpha: u8) -> Color {
+        Color {
+            red,
+            green,
+            blue,
+            alpha,
+        }
+    }
+
+    /// An opaque color from its `0xRRGGBB` form.
+    pub const fn from_u32(rgb: u32) -> Color {
+        Color::rgba((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, 255)
+    }
+
+    /// Builds a color from channels between 0 and 1. Values out of range are
+    /// clamped.
+    pub fn from_fractions(red: f64, green: f64, blue: f64, alpha: f64) -> Color {
+        Color::rgba(channel(red), channel(green), channel(blue), channel(alpha))
+    }
+
+    /// Parses the digits of `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`.
+    pub fn from_hex(digits: &str) -> Option<Color> {
+        let n = u32::from_str_radix(digits, 16).ok()?;
+        // Short forms repeat every digit, so `f` means `ff`
+        let short = |shift: u32| ((n >> shift) & 0xf) as u8 * 0x11;
+        let long = |shift: u32| (n >> shift) as u8;
+        match digits.len() {
+            3 => Some(Color::rgba(short(8), short(4), short(0), 255)),
+            4 => Some(Color::rgba(short(12), short(8), short(4), short(0))),
+            6 => Some(Color::rgba(long(16), long(8), long(0), 255)),
+            8 => Some(Color::rgba(long(24), long(16), long(8), long(0))),
+            _ => None,
+        }
+    }
+
+    /// Looks up a CSS named color, or `transparent`.
+    pub fn named(name: &str) -> Option<Color> {
+        if name == "transparent" {
+            return Some(Color::TRANSPARENT);
+        }
+        NAMED_COLORS
+            .binary_search_by_key(&name, |&(name, _)| name)
+            .ok()
+            .map(|i| Color::from_u32(NAMED_COLORS[i].1))
+    }
+
+    /// Builds a color from a hue in degrees and saturation, lightness and
+    /// alpha between 0 and 1. Values out of range are clamped.
+    pub fn from_hsla(hue: f64, saturation: f64, lightness: f64, alpha: f64) -> Color {
+        let hue = hue.rem_euclid(360.0) / 60.0;
+        let saturation = saturation.clamp(0.0, 1.0);
+        let lightness = lightness.clamp(0.0, 1.0);
+        let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+        let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+        let (r, g, b) = match hue as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let m = lightness - chroma / 2.0;
+        Color::from_fractions(r + m, g + m, b + m, alpha)
+    }
+
+    /// The hue in degrees and the saturation and lightness between 0 and 1.
+    pub fn to_hsl(self) -> (f64, f64, f64) {
+        let [r, g, b] = [self.red, self.green, self.blue].map(|c| c as f64 / 255.0);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let lightness = (max + min) / 2.0;
+        let chroma = max - min;
+        if chroma == 0.0 {
+            return (0.0, 0.0, lightness);
+        }
+        let saturation = chroma / (1.0 - (2.0 * lightness - 1.0).abs());
+        let hue = if max == r {
+            ((g - b) / chroma).rem_euclid(6.0)
+        } else if max == g {
+            (b - r) / chroma + 2.0
+        } else {
+            (r - g) / chroma + 4.0
+        };
+        (hue * 60.0, saturation, lightness)
+    }
+
+    /// Raises the lightness by `amount`, between -1 and 1, like Sass'
+    /// `lighten`. A negative amount darkens.
+    pub fn lighten(self, amount: f64) -> Color {
+        let (hue, saturation, lightness) = self.to_hsl();
+        Color::from_hsla(
+            hue,
+            saturation,
+            lightness + amount,
+            self.alpha as f64 / 255.0,
+        )
+    }
+
+    pub fn darken(self, amount: f64) -> Color {
+        self.lighten(-amount)
+    }
+
+    /// The color in `0xRRGGBB` form, without its alpha.
+    pub fn to_u32(self) -> u32 {
+        (self.red as u32) << 16 | (self.green as u32) << 8 | self.blue as u32
+    }
+}
+
+/// Converts a channel between 0 and 1 to a byte.
+fn channel(value: f64) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:06x}", self.to_u32())?;
+        if self.alpha != 255 {
+            write!(f, "{:02x}", self.alpha)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod color_tests {
+    use super::*;
+
+    #[test]
+    fn hex() {
+        assert_eq!(
+            Color::from_hex("f80"),
+            Some(Color::rgba(0xff, 0x88, 0, 255))
+        );
+        assert_eq!(
+            Color::from_hex("f808"),
+            Some(Color::rgba(0xff, 0x88, 0, 0x88))
+        );
+        assert_eq!(
+            Color::from_hex("12AB34"),
+            Some(Color::rgba(0x12, 0xab, 0x34, 255))
+        );
+        assert_eq!(
+            Color::from_hex("12ab3480"),
+            Some(Color::rgba(0x12, 0xab, 0x34, 0x80))
+        );
+        assert_eq!(Color::from_hex("12345"), None);
+        assert_eq!(
+            Color::from_hex("12ab3480").unwrap().to_string(),
+            "#12ab3480"
+        );
+        assert_eq!(Color::from_hex("f80").unwrap().to_string(), "#ff8800");
+    }
+
+    #[test]
+    fn named() {
+        assert!(NAMED_COLORS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(Color::named("orange"), Some(Color::from_u32(0xffa500)));
+        assert_eq!(Color::named("yellowgreen"), Some(Color::from_u32(0x9acd32)));
+        assert_eq!(Color::named("transparent"), Some(Color::TRANSPARENT));
+        assert_eq!(Color::named("hidden"), None);
+    }
+
+    #[test]
+    fn hsl() {
+        assert_eq!(
+            Color::from_hsla(0.0, 1.0, 0.5, 1.0),
+            Color::from_u32(0xff0000)
+        );
+        assert_eq!(
+            Color::from_hsla(120.0, 1.0, 0.25, 1.0),
+            Color::from_u32(0x008000)
+        );
+        assert_eq!(
+            Color::from_hsla(240.0, 1.0, 0.5, 0.5),
+            Color::rgba(0, 0, 255, 128)
+        );
+        assert_eq!(
+            Color::from_hsla(-60.0, 0.0, 1.0, 1.0),
+            Color::from_u32(0xffffff)
+        );
+        let orange = Color::from_u32(0xffa500);
+        let (h, s, l) = orange.to_hsl();
+        assert_eq!(Color::from_hsla(h, s, l, 1.0), orange);
+    }
+
+    #[test]
+    fn lighten() {
+        let red = Color::from_u32(0xff0000);
+        assert_eq!(red.darken(0.2), Color::from_u32(0x990000));
+        assert_eq!(red.lighten(0.2), Color::from_u32(0xff6666));
+        assert_eq!(red.lighten(1.0), Color::from_u32(0xffffff));
+        assert_eq!(Color::rgba(255, 0, 0, 100).darken(0.5).alpha, 100);
+    }
+}
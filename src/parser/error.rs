@@ -0,0 +1,99 @@
+use peg::str::LineCol;
+use peg::Parse;
+use std::fmt;
+
+/// The part of a document a parse error came from.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Section {
+    Document,
+    Style,
+    Body,
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Section::Document => write!(f, "document"),
+            Section::Style => write!(f, "<style>"),
+            Section::Body => write!(f, "<body>"),
+        }
+    }
+}
+
+/// A parse error with its position mapped back to the original file.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    pub section: Section,
+    /// Line in the original file (1-indexed)
+    pub line: usize,
+    /// Column in the original file (1-indexed)
+    pub column: usize,
+    /// Byte offset in the original file
+    pub offset: usize,
+    /// Sorted list of the tokens that would have been accepted
+    pub expected: Vec<String>,
+    /// The full source line the error points at, used for rendering
+    pub source_line: String,
+}
+
+impl ParseError {
+    /// Builds a `ParseError` from an error produced by parsing `source[base..]`
+    /// (or a substring starting there), relocating it into `source`.
+    pub(crate) fn new(
+        section: Section,
+        error: peg::error::ParseError<LineCol>,
+        source: &str,
+        base: usize,
+    ) -> Self {
+        let offset = base + error.location.offset;
+        let LineCol { line, column, .. } = source.position_repr(offset);
+        let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[offset..]
+            .find('\n')
+            .map_or(source.len(), |i| offset + i);
+        let mut expected = error
+            .expected
+            .tokens()
+            .map(|t| t.to_string())
+            .collect::<Vec<String>>();
+        expected.sort();
+        ParseError {
+            section,
+            line,
+            column,
+            offset,
+            expected,
+            source_line: source[line_start..line_end].trim_end().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.expected.len() {
+            0 => write!(f, "unexpected input in {}", self.section)?,
+            1 => write!(f, "expected {} in {}", self.expected[0], self.section)?,
+            _ => write!(
+                f,
+                "expected one of {} in {}",
+                self.expected.join(", "),
+                self.section
+            )?,
+        }
+        let gutter = " ".repeat(self.line.to_string().len());
+        // Keep tabs in the padding so the caret lines up with the source line
+        let padding = self
+            .source_line
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        writeln!(f)?;
+        writeln!(f, "{}--> {}:{}", gutter, self.line, self.column)?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.source_line)?;
+        write!(f, "{} | {}^", gutter, padding)
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -1,5 +1,10 @@
 pub(crate) mod asml_parser;
 pub(crate) mod ass_parser;
+pub(crate) mod color;
+pub(crate) mod error;
+#[allow(clippy::module_inception)]
 pub(crate) mod parser;
 
-pub use parser::parser::parser;
+pub use color::Color;
+pub use error::{ParseError, Section};
+pub use parser::parser;
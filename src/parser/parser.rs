@@ -1,19 +1,101 @@
 use crate::parser::asml_parser::{asml_parser::tag, Element};
-use crate::parser::ass_parser::{ass_parser::stylesheet, Style};
+use crate::parser::ass_parser::{ass_parser::stylesheet, Stylesheet};
+use crate::parser::error::{ParseError, Section};
 
 peg::parser! {
-    pub grammar parser() for str {
+    grammar document() for str {
         rule comment() = "<#" (!"#>"[c])* "#>"
 
         rule whitespace() = quiet!{([c if c.is_whitespace()]+ / comment())+ }
 
-        rule parse_styles() -> Vec<Style>
-            = "<style>" s:$([c if c != '<']+) "</style>" { stylesheet(s.trim()).expect("bad style") }
+        rule parse_styles() -> (usize, &'input str)
+            = "<style>" start:position!() s:$((!"</style>" [_])*) "</style>" { (start, s) }
 
-        rule parse_body() -> Element
-            = "<body>" b:$([c]+) { tag(&(String::from("<body>") + b)).expect("bad body") }
+        rule parse_body() -> usize
+            = start:position!() "<body>" [_]* { start }
 
-        pub rule parser() -> (Element, Vec<Style>)
-            = whitespace()* styles:parse_styles()? whitespace()* body:parse_body() whitespace()* { (body, styles.unwrap()) }
+        // Only finds where each section starts; the sections themselves are
+        // handed to their own grammars so errors can be mapped back here
+        pub rule document() -> (Option<(usize, &'input str)>, usize)
+            = whitespace()* styles:parse_styles()? whitespace()* body:parse_body() { (styles, body) }
+    }
+}
+
+pub fn parser(input: &str) -> Result<(Element, Stylesheet), ParseError> {
+    let (styles, body_start) =
+        document::document(input).map_err(|e| ParseError::new(Section::Document, e, input, 0))?;
+    let styles = match styles {
+        Some((start, s)) => {
+            let offset = start + (s.len() - s.trim_start().len());
+            stylesheet(s.trim()).map_err(|e| ParseError::new(Section::Style, e, input, offset))?
+        }
+        None => Stylesheet::default(),
+    };
+    let body = tag(&input[body_start..])
+        .map_err(|e| ParseError::new(Section::Body, e, input, body_start))?;
+    Ok((body, styles))
+}
+
+#[cfg(test)]
+mod parser_tests {
+    use super::*;
+
+    #[test]
+    fn style_error_location() {
+        let input = "<style>\n    div {\n        width == 20\n    }\n</style>\n<body></body>";
+        let error = parser(input).unwrap_err();
+        assert_eq!(error.section, Section::Style);
+        assert_eq!((error.line, error.column), (3, 16));
+        assert_eq!(error.source_line, "        width == 20");
+    }
+
+    #[test]
+    fn body_error_location() {
+        let input = "<style>\n</style>\n<body>\n  <h1(a></h1>\n</body>";
+        let error = parser(input).unwrap_err();
+        assert_eq!(error.section, Section::Body);
+        assert_eq!((error.line, error.column), (4, 8));
+        assert_eq!(error.source_line, "  <h1(a></h1>");
+    }
+
+    #[test]
+    fn missing_body() {
+        let error = parser("<style></style>").unwrap_err();
+        assert_eq!(error.section, Section::Document);
+        assert_eq!(error.expected, vec!["\"<body>\"".to_string()]);
+    }
+
+    #[test]
+    fn rendered_diagnostic() {
+        let input = "<style>\n  div { width = }\n</style>\n<body></body>";
+        let rendered = parser(input).unwrap_err().to_string();
+        let caret = rendered.lines().last().unwrap();
+        assert!(rendered.contains(" --> 2:"));
+        assert!(rendered.contains("2 |   div { width = }"));
+        assert_eq!(caret.find('^'), rendered.lines().nth(3).unwrap().find('}'));
+    }
+
+    #[test]
+    fn no_styles() {
+        let (body, styles) = parser("<body></body>").unwrap();
+        assert_eq!(styles, Stylesheet::default());
+        assert_eq!(
+            body,
+            Element::Tag {
+                traits: vec![crate::parser::asml_parser::Trait {
+                    name: "body".to_string(),
+                    args: Vec::new(),
+                }],
+                id: None,
+                children: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn example_document() {
+        if let Err(e) = parser(include_str!("../../documents/examples/ex1.ass")) {
+            panic!("{}", e);
+        }
     }
 }
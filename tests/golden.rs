@@ -0,0 +1,171 @@
+//! Renders every `.asml` fixture under `tests/` at several window sizes and
+//! compares the result against the reference images in `tests/golden/`.
+//!
+//! Run with `UPDATE_GOLDEN=1` to write the current output as the new
+//! references. On a mismatch, the rendered image and a diff highlighting the
+//! differing pixels in red are written to the target directory.
+
+use ass::display::render_document;
+use std::env;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+const SIZES: [(usize, usize); 3] = [(320, 240), (800, 600), (1280, 720)];
+
+/// How far apart the channels of two pixels may be before they differ.
+const TOLERANCE: u8 = 8;
+
+struct Image {
+    width: usize,
+    height: usize,
+    /// 8 bit RGB
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    fn read(path: &Path) -> Image {
+        let decoder = png::Decoder::new(File::open(path).unwrap());
+        let (info, mut reader) = decoder.read_info().unwrap();
+        assert_eq!(info.color_type, png::ColorType::RGB, "{:?}", path);
+        let mut pixels = vec![0; info.buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        Image {
+            width: info.width as usize,
+            height: info.height as usize,
+            pixels,
+        }
+    }
+
+    fn write(&self, path: &Path) {
+        let mut encoder = png::Encoder::new(
+            File::create(path).unwrap(),
+            self.width as u32,
+            self.height as u32,
+        );
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&self.pixels)
+            .unwrap();
+    }
+}
+
+fn fixtures(dir: &Path) -> Vec<PathBuf> {
+    let mut fixtures = Vec::new();
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            fixtures.extend(self::fixtures(&path));
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "asml")
+        {
+            fixtures.push(path);
+        }
+    }
+    fixtures.sort();
+    fixtures
+}
+
+/// Returns the number of differing pixels and an image of `expected`, faded,
+/// with the differing pixels in red.
+fn diff(expected: &Image, actual: &Image) -> (usize, Image) {
+    let mut count = 0;
+    let pixels = expected
+        .pixels
+        .chunks(3)
+        .zip(actual.pixels.chunks(3))
+        .flat_map(|(e, a)| {
+            if e.iter()
+                .zip(a)
+                .any(|(e, a)| e.max(a) - e.min(a) > TOLERANCE)
+            {
+                count += 1;
+                vec![255, 0, 0]
+            } else {
+                e.iter().map(|c| 191 + c / 4).collect()
+            }
+        })
+        .collect();
+    (
+        count,
+        Image {
+            width: expected.width,
+            height: expected.height,
+            pixels,
+        },
+    )
+}
+
+#[test]
+fn golden() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let output = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&output).unwrap();
+    let mut failures = Vec::new();
+
+    for fixture in fixtures(&root) {
+        let source = fs::read_to_string(&fixture).unwrap();
+        let stem = fixture
+            .strip_prefix(&root)
+            .unwrap()
+            .with_extension("")
+            .to_string_lossy()
+            .replace('/', "_");
+        for &(width, height) in &SIZES {
+            let name = format!("{}-{}x{}.png", stem, width, height);
+            let scene = match render_document(&source, width, height) {
+                Ok(scene) => scene,
+                Err(e) => {
+                    failures.push(format!("{}: {}", name, e));
+                    continue;
+                }
+            };
+            let actual = Image {
+                width,
+                height,
+                pixels: scene
+                    .buffer()
+                    .iter()
+                    .flat_map(|pixel| pixel.to_be_bytes()[1..].to_vec())
+                    .collect(),
+            };
+            let reference = root.join("golden").join(&name);
+            if update {
+                actual.write(&reference);
+                continue;
+            }
+            if !reference.exists() {
+                failures.push(format!(
+                    "{}: no reference image, run with UPDATE_GOLDEN=1 to create it",
+                    name
+                ));
+                continue;
+            }
+            let expected = Image::read(&reference);
+            if (expected.width, expected.height) != (width, height) {
+                failures.push(format!(
+                    "{}: reference is {}x{}",
+                    name, expected.width, expected.height
+                ));
+                continue;
+            }
+            let (count, diff) = diff(&expected, &actual);
+            if count > 0 {
+                actual.write(&output.join(&name));
+                diff.write(&output.join(format!("{}.diff.png", name.trim_end_matches(".png"))));
+                failures.push(format!(
+                    "{}: {} pixels differ, see {}",
+                    name,
+                    count,
+                    output.display()
+                ));
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}